@@ -0,0 +1,204 @@
+use crate::constants::*;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+// 同一进程内并发写入同一文件时区分临时文件
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// 与目标文件在同一目录下的隐藏临时文件，保证重命名不会跨文件系统
+pub fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}-{}{}",
+        name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+        ATOMIC_TEMP_SUFFIX
+    ))
+}
+
+// 写入到临时文件、尚未替换目标文件的新内容，未提交就丢弃时删除临时文件
+// 内容可以分多次写入，写完后调用 finish 同步到磁盘
+pub struct StagedFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    committed: bool,
+}
+
+impl StagedFile {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        // 目标是符号链接时写入链接指向的文件，保留链接本身
+        let path = match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => fs::canonicalize(path)?,
+            _ => path.to_path_buf(),
+        };
+
+        let temp_path = temp_path_for(&path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        Ok(Self {
+            path,
+            temp_path,
+            writer: Some(BufWriter::with_capacity(STREAM_BUFFER_SIZE, file)),
+            committed: false,
+        })
+    }
+
+    pub fn new(path: &Path, content: &[u8]) -> Result<Self, Error> {
+        let mut staged = Self::create(path)?;
+        staged.write_all(content)?;
+        staged.finish()?;
+        Ok(staged)
+    }
+
+    // 写入完成：保留原文件的权限（新建的文件使用默认权限），并同步到磁盘
+    pub fn finish(&mut self) -> Result<(), Error> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        match fs::metadata(&self.path) {
+            Ok(metadata) => file.set_permissions(metadata.permissions())?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        file.sync_all()
+    }
+
+    // 重命名覆盖目标文件
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.finish()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+
+        // 同步所在目录，使重命名本身也写入磁盘；Windows 上无法打开目录，忽略失败
+        if let Some(dir) = self.path.parent()
+            && let Ok(dir) = File::open(dir)
+        {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+
+    // 写完后才能确定目标路径时（如按内容的哈希命名），提交到同一目录下的另一个路径
+    pub fn commit_to(mut self, path: &Path) -> Result<(), Error> {
+        self.path = path.to_path_buf();
+        self.commit()
+    }
+}
+
+impl Write for StagedFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        match &mut self.writer {
+            Some(writer) => writer.write(buf),
+            None => Err(Error::other("Staged file is already finished")),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            // 先关闭文件，Windows 上无法删除打开中的文件
+            self.writer.take();
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+// 先写入临时文件并同步到磁盘，再重命名覆盖目标文件
+// 写入中途出错、磁盘已满或进程崩溃时，目标文件保持原样，不会只写入一半
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), Error> {
+    StagedFile::new(path, content)?.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cyrene-test-atomic-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn content_is_replaced_without_leftovers() {
+        let dir = test_dir("replace");
+        let path = dir.join("post.md");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(file_names(&dir), ["post.md"]);
+
+        // 没有提交就丢弃时原文件不变，临时文件被删除
+        let mut staged = StagedFile::create(&path).unwrap();
+        staged.write_all(b"discarded").unwrap();
+        drop(staged);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(file_names(&dir), ["post.md"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_are_kept() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("permissions");
+        let path = dir.join("post.md");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_written_through() {
+        let dir = test_dir("symlink");
+        let target = dir.join("target.md");
+        let link = dir.join("link.md");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+        assert!(
+            fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(file_names(&dir), ["link.md", "target.md"]);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::atomic_write::write_atomic;
+use crate::config::{ProjectConfig, has_content_extension};
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 use std::{
@@ -25,9 +27,20 @@ pub fn backup_files_as_tar_zst(
     base_path: String,
     output_path: String,
     zstd_level: i32,
+    extensions: Option<Vec<String>>,
 ) -> Result<(), String> {
     let base_dir = Path::new(&base_path);
 
+    // 只备份匹配扩展名的内容文件，未指定时使用项目配置
+    let extensions = match extensions {
+        Some(extensions) => extensions,
+        None => ProjectConfig::load(base_dir)?.extensions,
+    };
+    let file_list: Vec<String> = file_list
+        .into_iter()
+        .filter(|rel_path| has_content_extension(Path::new(rel_path), &extensions))
+        .collect();
+
     let tar_zst_path = Path::new(&output_path);
     let tar_zst_file = File::create(tar_zst_path).map_err(|e| e.to_string())?;
     let buf_writer = BufWriter::new(tar_zst_file);
@@ -127,7 +140,7 @@ pub fn restore_files_from_tar_zst(
     restore_base_path: String,
 ) -> Result<RestoreResult, String> {
     use std::fs::create_dir_all;
-    use std::io::{BufReader, Write};
+    use std::io::BufReader;
     
     let backup_file = File::open(&backup_path).map_err(|e| e.to_string())?;
     let buf_reader = BufReader::new(backup_file);
@@ -193,9 +206,8 @@ pub fn restore_files_from_tar_zst(
                 create_dir_all(parent).map_err(|e| e.to_string())?;
             }
             
-            // 写入文件
-            let mut output_file = File::create(&full_restore_path).map_err(|e| e.to_string())?;
-            output_file.write_all(&file_data).map_err(|e| e.to_string())?;
+            // 写入文件，先写入临时文件再替换，避免恢复中断时留下不完整的文件
+            write_atomic(&full_restore_path, &file_data).map_err(|e| e.to_string())?;
             
             success_count += 1;
         } else {
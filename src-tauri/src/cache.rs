@@ -0,0 +1,361 @@
+use crate::{
+    ParsedFile, atomic_write::write_atomic, constants::*, datetime::restore_datetime_tags,
+    parse_file_bytes, revision::FileRevision,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Error,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager};
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashMap<K, V> = HashMap<K, V, ahash::RandomState>;
+type AHashHashSet<T> = HashSet<T, ahash::RandomState>;
+
+// 单个文件的缓存，修改时间和缓存时间为自UNIX纪元起的秒数和纳秒数
+#[derive(Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    mtime: (u64, u32),
+    size: u64,
+    cached_at: (u64, u32),
+    parsed: ParsedFile,
+}
+
+impl CacheEntry {
+    // 缓存时文件刚被修改过，之后在同一时间内的修改可能不改变修改时间，不能只凭修改时间判断
+    fn is_racy(&self) -> bool {
+        let (secs, nanos) = self.mtime;
+        let mtime = Duration::new(secs, nanos);
+        let (secs, nanos) = self.cached_at;
+        Duration::new(secs, nanos) < mtime + Duration::from_secs(PARSE_CACHE_RACY_SECS)
+    }
+
+    // 只有缓存时间不同、且新旧条目都可以凭修改时间判断时，不需要写回磁盘
+    fn differs_from(&self, other: &Self) -> bool {
+        self.mtime != other.mtime
+            || self.size != other.size
+            || self.parsed.revision != other.parsed.revision
+            || self.is_racy() != other.is_racy()
+    }
+}
+
+// 缓存文件的内容，版本不一致时整个缓存作废
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: String,
+    entries: AHashHashMap<String, CacheEntry>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    // 应用缓存目录不可用时为 None，此时只在内存中缓存
+    path: Option<PathBuf>,
+    loaded: bool,
+    dirty: bool,
+    // 上次与磁盘上的缓存一致的时间
+    synced_at: Option<Instant>,
+    entries: AHashHashMap<String, CacheEntry>,
+}
+
+// 保存在磁盘上的解析缓存，按文件的绝对路径索引
+#[derive(Default)]
+pub struct ParseCache {
+    state: Mutex<CacheState>,
+}
+
+// 应用版本和解析逻辑版本共同决定缓存是否可用
+fn cache_version() -> String {
+    format!("{}+{}", env!("CARGO_PKG_VERSION"), PARSE_CACHE_VERSION)
+}
+
+fn mtime_of(modified: SystemTime) -> Option<(u64, u32)> {
+    let duration = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some((duration.as_secs(), duration.subsec_nanos()))
+}
+
+fn read_cache_file(path: &Path) -> Option<AHashHashMap<String, CacheEntry>> {
+    let content = fs::read(path).ok()?;
+    let cache = match serde_json::from_slice::<CacheFile>(&content) {
+        Ok(cache) => cache,
+        Err(e) => {
+            eprintln!("Ignoring unreadable parse cache: {}", e);
+            return None;
+        }
+    };
+    if cache.version != cache_version() {
+        return None;
+    }
+
+    let mut entries = cache.entries;
+    // JSON中的日期时间是 {"!datetime": "..."}，还原为带标签的值
+    for entry in entries.values_mut() {
+        for value in entry.parsed.frontmatter.values_mut() {
+            restore_datetime_tags(value);
+        }
+    }
+    Some(entries)
+}
+
+impl ParseCache {
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // 第一次使用时从应用缓存目录读取缓存
+    pub fn load(&self, app: &AppHandle) {
+        let mut state = self.lock();
+        if state.loaded {
+            return;
+        }
+        state.loaded = true;
+        state.path = app
+            .path()
+            .app_cache_dir()
+            .ok()
+            .map(|dir| dir.join(PARSE_CACHE_FILE));
+        if let Some(entries) = state.path.as_deref().and_then(read_cache_file) {
+            state.entries = entries;
+        }
+        state.synced_at = Some(Instant::now());
+    }
+
+    pub fn read_and_parse(&self, file_path: &str) -> ParsedFile {
+        self.lookup(file_path).unwrap_or_else(|e| ParsedFile {
+            error: Some(e.into()),
+            ..Default::default()
+        })
+    }
+
+    // 修改时间和大小都没变时直接使用缓存；否则读取文件，内容的哈希没变时（如只是被touch过）也沿用缓存
+    // 缓存时文件刚被修改过的条目总是重新计算哈希；文件已被删除时移除它的缓存
+    fn lookup(&self, file_path: &str) -> Result<ParsedFile, Error> {
+        let metadata = fs::metadata(file_path).inspect_err(|_| self.forget(file_path))?;
+        let modified = metadata.modified().ok();
+        let mtime = modified.and_then(mtime_of);
+        let cached = self.lock().entries.get(file_path).cloned();
+        if let Some(entry) = cached.as_ref().filter(|entry| {
+            Some(entry.mtime) == mtime && entry.size == metadata.len() && !entry.is_racy()
+        }) {
+            return Ok(entry.parsed.clone());
+        }
+
+        let bytes = fs::read(file_path)?;
+        let size = bytes.len() as u64;
+        let revision = FileRevision::of(&bytes, modified);
+        let parsed = match &cached {
+            Some(entry) if entry.parsed.revision.sha256 == revision.sha256 => ParsedFile {
+                revision,
+                ..entry.parsed.clone()
+            },
+            _ => ParsedFile {
+                revision,
+                ..parse_file_bytes(bytes)
+            },
+        };
+
+        // 无法获取修改时间的文件不缓存
+        if let Some(mtime) = mtime {
+            let entry = CacheEntry {
+                mtime,
+                size,
+                cached_at: mtime_of(SystemTime::now()).unwrap_or_default(),
+                parsed: parsed.clone(),
+            };
+            let mut state = self.lock();
+            if cached.is_none_or(|cached| cached.differs_from(&entry)) {
+                state.dirty = true;
+            }
+            state.entries.insert(file_path.to_owned(), entry);
+        }
+        Ok(parsed)
+    }
+
+    fn forget(&self, file_path: &str) {
+        let mut state = self.lock();
+        if state.entries.remove(file_path).is_some() {
+            state.dirty = true;
+        }
+    }
+
+    // 文件或目录已被删除或移走时调用，移除其中所有文件的缓存并返回它们的路径
+    pub fn forget_under(&self, path: &Path) -> Vec<String> {
+        let mut state = self.lock();
+        let forgotten: Vec<String> = state
+            .entries
+            .keys()
+            .filter(|file_path| Path::new(file_path).starts_with(path))
+            .cloned()
+            .collect();
+        for file_path in &forgotten {
+            state.entries.remove(file_path);
+        }
+        if !forgotten.is_empty() {
+            state.dirty = true;
+        }
+        forgotten
+    }
+
+    // 完整扫描 root 之后调用，移除其中本次没有扫描到的文件（已删除、移动或被排除）的缓存
+    pub fn forget_unseen(&self, root: &Path, seen: &AHashHashSet<String>) {
+        let mut state = self.lock();
+        let count = state.entries.len();
+        state.entries.retain(|file_path, _| {
+            seen.contains(file_path) || !Path::new(file_path).starts_with(root)
+        });
+        if state.entries.len() != count {
+            state.dirty = true;
+        }
+    }
+
+    // 有新的解析结果时写回磁盘，写入失败只影响下次启动的速度
+    pub fn save(&self) {
+        let mut state = self.lock();
+        if !state.dirty {
+            return;
+        }
+        let Some(path) = state.path.clone() else {
+            return;
+        };
+
+        let cache = CacheFile {
+            version: cache_version(),
+            entries: std::mem::take(&mut state.entries),
+        };
+        let result = serde_json::to_vec(&cache)
+            .map_err(Error::from)
+            .and_then(|content| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                write_atomic(&path, &content)
+            });
+        state.entries = cache.entries;
+        match result {
+            Ok(()) => {
+                state.dirty = false;
+                state.synced_at = Some(Instant::now());
+            }
+            Err(e) => eprintln!("Failed to save parse cache: {}", e),
+        }
+    }
+
+    // 距离上次写回超过间隔时才写回，用于缓存频繁更新的场景（如文件监视）
+    pub fn save_if_due(&self, interval: Duration) {
+        let due = self
+            .lock()
+            .synced_at
+            .is_none_or(|synced_at| synced_at.elapsed() >= interval);
+        if due {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn write_file(path: &Path, content: &str, modified: SystemTime) {
+        fs::write(path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    fn title_of(parsed: &ParsedFile) -> Option<&str> {
+        parsed.frontmatter.get("title")?.as_str()
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cyrene-test-cache-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unchanged_files_do_not_dirty_the_cache() {
+        let dir = test_dir("dirty");
+        let path = dir.join("post.md");
+        let file_path = path.to_str().unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        write_file(&path, "---\ntitle: a\n---\n", an_hour_ago);
+
+        let cache = ParseCache::default();
+        assert_eq!(title_of(&cache.read_and_parse(file_path)), Some("a"));
+        assert!(cache.lock().dirty);
+
+        cache.lock().dirty = false;
+        assert_eq!(title_of(&cache.read_and_parse(file_path)), Some("a"));
+        assert!(!cache.lock().dirty);
+
+        // 只是被touch过：内容没变，修改时间变了，需要写回新的修改时间
+        write_file(
+            &path,
+            "---\ntitle: a\n---\n",
+            an_hour_ago + Duration::from_secs(60),
+        );
+        assert_eq!(title_of(&cache.read_and_parse(file_path)), Some("a"));
+        assert!(cache.lock().dirty);
+    }
+
+    #[test]
+    fn recently_modified_files_are_hashed_again() {
+        let dir = test_dir("racy");
+        let path = dir.join("post.md");
+        let file_path = path.to_str().unwrap();
+        let modified = SystemTime::now();
+        write_file(&path, "---\ntitle: a\n---\n", modified);
+
+        let cache = ParseCache::default();
+        assert_eq!(title_of(&cache.read_and_parse(file_path)), Some("a"));
+
+        // 同一时间内再次修改，大小和修改时间都与缓存相同
+        write_file(&path, "---\ntitle: b\n---\n", modified);
+        assert_eq!(title_of(&cache.read_and_parse(file_path)), Some("b"));
+    }
+
+    #[test]
+    fn deleted_and_unseen_files_are_forgotten() {
+        let dir = test_dir("forget");
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let paths: Vec<String> = ["a.md", "b.md", "c.md"]
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                write_file(&path, "---\ntitle: a\n---\n", an_hour_ago);
+                path.to_str().unwrap().to_owned()
+            })
+            .collect();
+        let outside = "/elsewhere/post.md".to_owned();
+
+        let cache = ParseCache::default();
+        for file_path in &paths {
+            cache.read_and_parse(file_path);
+        }
+        let entry = cache.lock().entries[&paths[0]].clone();
+        cache.lock().entries.insert(outside.clone(), entry);
+
+        fs::remove_file(&paths[0]).unwrap();
+        cache.lock().dirty = false;
+        assert!(cache.read_and_parse(&paths[0]).error.is_some());
+        assert!(!cache.lock().entries.contains_key(&paths[0]));
+        assert!(cache.lock().dirty);
+
+        // 本次扫描只找到了 b.md，目录之外的缓存不受影响
+        let seen = AHashHashSet::from_iter([paths[1].clone()]);
+        cache.forget_unseen(&dir, &seen);
+        let mut cached: Vec<String> = cache.lock().entries.keys().cloned().collect();
+        cached.sort_unstable();
+        assert_eq!(cached, [outside, paths[1].clone()]);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{constants::*, yaml_emit::YamlStyle};
+use serde::{Deserialize, Serialize};
+use serde_yaml_ng::Value;
+use std::{fs, path::Path};
+
+use indexmap::IndexMap;
+
+// 使用ahash作为哈希器的类型别名
+type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderPolicy {
+    // 不调整顺序：YAML保持原文中的顺序，新增的键在最后
+    #[default]
+    Preserve,
+    // 按键名排序，不区分大小写
+    Alphabetical,
+}
+
+// 保存时frontmatter中键的顺序，配置为 preserve、alphabetical 或键名列表
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyOrder {
+    Policy(OrderPolicy),
+    // 按列表中的顺序排列，不在列表中的键保持原有顺序排在后面
+    List(Vec<String>),
+}
+
+impl Default for KeyOrder {
+    fn default() -> Self {
+        KeyOrder::Policy(OrderPolicy::Preserve)
+    }
+}
+
+impl KeyOrder {
+    pub fn is_preserve(&self) -> bool {
+        *self == KeyOrder::Policy(OrderPolicy::Preserve)
+    }
+
+    pub fn apply(&self, data: &mut AHashIndexMap<String, Value>) {
+        match self {
+            KeyOrder::Policy(OrderPolicy::Preserve) => {}
+            KeyOrder::Policy(OrderPolicy::Alphabetical) => data.sort_by(|a, _, b, _| {
+                a.to_lowercase()
+                    .cmp(&b.to_lowercase())
+                    .then_with(|| a.cmp(b))
+            }),
+            KeyOrder::List(order) => data.sort_by_cached_key(|key, _| {
+                order
+                    .iter()
+                    .position(|listed| listed.eq_ignore_ascii_case(key))
+                    .unwrap_or(order.len())
+            }),
+        }
+    }
+}
+
+// 项目配置，存放在站点根目录的 .cyrene.yml 中，缺省时使用默认值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    // 需要处理的内容文件扩展名，不区分大小写
+    pub extensions: Vec<String>,
+    // 扫描时使用的 glob，相对于根目录匹配；include 为空表示不限制
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // 是否进入隐藏目录
+    pub include_hidden: bool,
+    // 是否遵循 .gitignore 和 .ignore 文件
+    pub respect_ignore_files: bool,
+    // 保存时是否将非UTF-8编码的文件转换为UTF-8
+    pub convert_to_utf8: bool,
+    // 批量保存时是否使用事务模式，任何文件无法写入时所有文件都保持原样
+    pub transactional_writes: bool,
+    // 保存时键的顺序
+    pub key_order: KeyOrder,
+    // YAML frontmatter的写出格式，不设置时使用默认格式
+    pub yaml_style: Option<YamlStyle>,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            extensions: vec![MD_EXTENSION.to_owned()],
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_hidden: false,
+            respect_ignore_files: true,
+            convert_to_utf8: false,
+            transactional_writes: false,
+            key_order: KeyOrder::default(),
+            yaml_style: None,
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn load(base_path: &Path) -> Result<Self, String> {
+        let config_path = base_path.join(PROJECT_CONFIG_FILE);
+        if !config_path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content =
+            fs::read_to_string(&config_path).map_err(|e| format!("{}{}", ERROR_CONFIG_READ, e))?;
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_yaml_ng::from_str(&content).map_err(|e| format!("{}{}", ERROR_CONFIG_READ, e))
+    }
+}
+
+// 判断文件扩展名是否在列表中，忽略大小写和扩展名开头的点
+pub fn has_content_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext))
+        })
+}
+
+#[tauri::command]
+pub fn load_project_config(base_path: String) -> Result<ProjectConfig, String> {
+    ProjectConfig::load(Path::new(&base_path))
+}
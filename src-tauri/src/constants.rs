@@ -1,11 +1,74 @@
 // 常用的字符串常量，避免重复分配
 pub const FRONTMATTER_DELIMITER: &str = "---";
+pub const YAML_DOCUMENT_END: &str = "...";
+pub const YAML_MERGE_KEY: &str = "<<";
+// 日期时间值的YAML标签
+pub const DATETIME_TAG: &str = "datetime";
+// Excel 1900日期系统的起点（序列号0），已包含1900年闰年错误的修正
+pub const EXCEL_EPOCH: (i64, i64, i64) = (1899, 12, 30);
+pub const TOML_FRONTMATTER_DELIMITER: &str = "+++";
+pub const JSON_FRONTMATTER_START: char = '{';
+pub const DEFAULT_JSON_INDENT: &str = "  ";
+pub const PELICAN_TITLE_KEY: &str = "title";
+pub const PELICAN_CONTINUATION_INDENT: &str = "    ";
+pub const UTF8_BOM: char = '\u{feff}';
 pub const EMPTY_YAML_OBJECT: &str = "{}";
 pub const COMMA_SEPARATOR: &str = ", ";
+// YAML流式集合 [a, b]、{a: b} 中的分隔符
+pub const FLOW_INDICATOR_CHARS: &[char] = &[',', '[', ']', '{', '}'];
+// YAML 1.1 中会被读作布尔值或空值的字符串，写出时加引号以兼容旧的解析器
+pub const YAML_AMBIGUOUS_PLAIN: &[&str] = &[
+    "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO", "on", "On", "ON", "off", "Off",
+    "OFF", "~", "null", "Null", "NULL",
+];
+
+// 流式扫描时每批发送的文件数
+pub const STREAM_CHUNK_SIZE: usize = 256;
+
+// 解析缓存的文件名，保存在应用缓存目录中
+pub const PARSE_CACHE_FILE: &str = "parse-cache.json";
+// 解析结果的格式或解析逻辑变化时需要增加，使旧的缓存失效
+pub const PARSE_CACHE_VERSION: u32 = 6;
+// 文件在缓存前这么多秒内被修改过时，不能只凭修改时间判断之后是否又被修改
+pub const PARSE_CACHE_RACY_SECS: u64 = 2;
+
+// 文件监视的防抖时间（毫秒）和推送给前端的事件名
+pub const WATCH_DEBOUNCE_MS: u64 = 500;
+pub const FILE_CHANGE_EVENT: &str = "file-changed";
+// 文件监视期间解析缓存写回磁盘的最短间隔（秒），停止监视和退出应用时也会写回
+pub const CACHE_SAVE_INTERVAL_SECS: u64 = 60;
+
+// 遵循忽略文件时读取的文件，同一目录中排在前面的优先
+pub const IGNORE_FILES: &[&str] = &[".ignore", ".gitignore"];
+// 仓库中不提交的忽略规则，相对于仓库根目录
+pub const GIT_EXCLUDE_FILE: &str = ".git/info/exclude";
+
+// 原子写入时临时文件的后缀，临时文件以 . 开头，不会被扫描到
+pub const ATOMIC_TEMP_SUFFIX: &str = ".tmp";
+// 流式写入时读写文件使用的缓冲区大小，正文按此大小分块复制，不整体读入内存
+pub const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+// 预览修改时在frontmatter之后显示的正文行数，正文不会变化，不需要读取整个文件
+pub const PREVIEW_CONTEXT_LINES: usize = 3;
+
+// 写入记录保存在应用数据目录中，只保留最近的批次
+pub const JOURNAL_DIR: &str = "journal";
+pub const JOURNAL_BLOBS_DIR: &str = "blobs";
+pub const JOURNAL_BATCHES_DIR: &str = "batches";
+// 保存中的内容在得到哈希之前使用的文件名，实际写入以此命名的临时文件
+pub const JOURNAL_PENDING_BLOB: &str = "pending";
+pub const JOURNAL_MAX_BATCHES: usize = 50;
 
 // 文件扩展名
 pub const MD_EXTENSION: &str = "md";
 
+// 项目配置文件，位于站点根目录
+pub const PROJECT_CONFIG_FILE: &str = ".cyrene.yml";
+
+// Excel日期单元格的数字格式
+pub const EXCEL_DATE_FORMAT: &str = "yyyy-mm-dd";
+pub const EXCEL_DATETIME_FORMAT: &str = "yyyy-mm-dd hh:mm:ss";
+pub const EXCEL_TIME_FORMAT: &str = "hh:mm:ss";
+
 // 布尔值字符串
 pub const TRUE_VALUES: &[&str] = &["true", "True", "TRUE"];
 pub const FALSE_VALUES: &[&str] = &["false", "False", "FALSE"];
@@ -14,3 +77,8 @@ pub const FALSE_VALUES: &[&str] = &["false", "False", "FALSE"];
 pub const ERROR_EXCEL_SAVE: &str = "保存Excel文件时出错: ";
 pub const ERROR_EXCEL_READ: &str = "读取Excel文件时出错: ";
 pub const SUCCESS_EXCEL_SAVE: &str = "Excel文件已成功保存到: ";
+pub const ERROR_CONFIG_READ: &str = "读取项目配置文件时出错: ";
+pub const ERROR_INVALID_GLOB: &str = "无效的匹配模式: ";
+pub const ERROR_WATCH: &str = "监视目录时出错: ";
+pub const ERROR_JOURNAL: &str = "读取或保存写入记录时出错: ";
+pub const ERROR_BATCH_NOT_FOUND: &str = "找不到写入批次: ";
@@ -0,0 +1,196 @@
+use crate::constants::*;
+use serde_yaml_ng::{
+    Value,
+    value::{Tag, TaggedValue},
+};
+use toml::value::{Datetime, Time};
+
+// frontmatter中的日期时间值，保留原始文本以保持精度和时区
+// 在统一的 Value 中表示为带 !datetime 标签的字符串，传给前端时为 {"!datetime": "..."}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateTimeValue {
+    text: String,
+    datetime: Datetime,
+}
+
+// 公历日期到 1970-01-01 的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl DateTimeValue {
+    // 只接受完整的日期时间文本，如 2023-05-01、2023-05-01 12:00:00、2023-05-01T12:00:00+08:00
+    pub fn parse(text: &str) -> Option<Self> {
+        // 至少要有日期或带冒号的时间，避免把普通数字当成日期
+        if !text.contains(['-', ':']) {
+            return None;
+        }
+        let datetime = text.parse::<Datetime>().ok()?;
+        Some(Self {
+            text: text.to_owned(),
+            datetime,
+        })
+    }
+
+    // TOML中的日期时间值，按TOML的格式保存原始文本
+    pub fn from_toml(datetime: Datetime) -> Self {
+        Self {
+            text: datetime.to_string(),
+            datetime,
+        }
+    }
+
+    pub fn to_toml(&self) -> Datetime {
+        self.datetime
+    }
+
+    // 接受带标签的值，以及前端传回的 {"!datetime": "..."}
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == DATETIME_TAG => {
+                Self::parse(tagged.value.as_str()?)
+            }
+            Value::Mapping(mapping) if mapping.len() == 1 => {
+                let (key, value) = mapping.iter().next()?;
+                if key.as_str()?.strip_prefix('!')? != DATETIME_TAG {
+                    return None;
+                }
+                Self::parse(value.as_str()?)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Tagged(Box::new(TaggedValue {
+            tag: Tag::new(DATETIME_TAG),
+            value: Value::String(self.text),
+        }))
+    }
+
+    pub fn has_date(&self) -> bool {
+        self.datetime.date.is_some()
+    }
+
+    pub fn has_time(&self) -> bool {
+        self.datetime.time.is_some()
+    }
+
+    // 日期和时间相同，忽略时区，时间精确到毫秒
+    pub fn same_local_time(&self, other: &Self) -> bool {
+        let millis = |time: Option<Time>| {
+            time.map(|t| (t.hour, t.minute, t.second, t.nanosecond / 1_000_000))
+        };
+        self.datetime.date == other.datetime.date
+            && millis(self.datetime.time) == millis(other.datetime.time)
+    }
+
+    // 转换为Excel的日期序列号（1900日期系统），按原文中的本地时间计算，时区不参与计算
+    pub fn to_excel_serial(&self) -> f64 {
+        let days = self.datetime.date.map_or(0, |date| {
+            days_from_civil(
+                i64::from(date.year),
+                i64::from(date.month),
+                i64::from(date.day),
+            ) - days_from_civil(EXCEL_EPOCH.0, EXCEL_EPOCH.1, EXCEL_EPOCH.2)
+        });
+        let seconds = self.datetime.time.map_or(0.0, |time| {
+            f64::from(time.hour) * 3600.0
+                + f64::from(time.minute) * 60.0
+                + f64::from(time.second)
+                + f64::from(time.nanosecond) / 1e9
+        });
+        days as f64 + seconds / 86400.0
+    }
+
+    // 根据Excel的日期序列号创建，时间四舍五入到毫秒
+    pub fn from_excel_serial(serial: f64, has_date: bool, has_time: bool) -> Option<Self> {
+        if !serial.is_finite() || serial < 0.0 {
+            return None;
+        }
+        let total_millis = (serial * 86_400_000.0).round() as i64;
+        let days = total_millis.div_euclid(86_400_000);
+        let millis = total_millis.rem_euclid(86_400_000);
+
+        let mut text = String::new();
+        if has_date {
+            let epoch = days_from_civil(EXCEL_EPOCH.0, EXCEL_EPOCH.1, EXCEL_EPOCH.2);
+            let (year, month, day) = civil_from_days(epoch + days);
+            text.push_str(&format!("{:04}-{:02}-{:02}", year, month, day));
+        }
+        if has_time {
+            if has_date {
+                text.push(' ');
+            }
+            text.push_str(&format!(
+                "{:02}:{:02}:{:02}",
+                millis / 3_600_000,
+                millis / 60_000 % 60,
+                millis / 1000 % 60
+            ));
+            if millis % 1000 != 0 {
+                text.push_str(&format!(".{:03}", millis % 1000));
+            }
+        }
+        Self::parse(&text)
+    }
+}
+
+// 将能解析为日期时间的字符串标记为日期时间，用于读取YAML、JSON和Pelican的frontmatter
+pub fn tag_datetimes(value: &mut Value) {
+    match value {
+        Value::String(s) => {
+            if let Some(datetime) = DateTimeValue::parse(s) {
+                *value = datetime.into_value();
+            }
+        }
+        Value::Sequence(seq) => seq.iter_mut().for_each(tag_datetimes),
+        Value::Mapping(mapping) => mapping.values_mut().for_each(tag_datetimes),
+        _ => {}
+    }
+}
+
+// 将 {"!datetime": "..."} 还原为带标签的值，普通字符串保持不变，用于读取缓存
+pub fn restore_datetime_tags(value: &mut Value) {
+    if let Some(datetime) = DateTimeValue::from_value(value) {
+        *value = datetime.into_value();
+        return;
+    }
+    match value {
+        Value::Sequence(seq) => seq.iter_mut().for_each(restore_datetime_tags),
+        Value::Mapping(mapping) => mapping.values_mut().for_each(restore_datetime_tags),
+        _ => {}
+    }
+}
+
+// 将日期时间还原为原始文本，用于写入文件
+pub fn untag_datetimes(value: &mut Value) {
+    if let Some(datetime) = DateTimeValue::from_value(value) {
+        *value = Value::String(datetime.text);
+        return;
+    }
+    match value {
+        Value::Sequence(seq) => seq.iter_mut().for_each(untag_datetimes),
+        Value::Mapping(mapping) => mapping.values_mut().for_each(untag_datetimes),
+        _ => {}
+    }
+}
@@ -0,0 +1,458 @@
+use crate::{
+    constants::*,
+    encoding::{bom_of, detect_encoding, encode},
+    format::{FrontmatterFormat, FrontmatterScanner, LineRole},
+    parse_json_frontmatter,
+};
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Error, Read, Seek, SeekFrom, Write},
+};
+
+// 一个内容文件：编码、frontmatter 以及正文的位置
+pub struct Document {
+    pub encoding: &'static Encoding,
+    // 没有完整的frontmatter时为 None
+    pub format: Option<FrontmatterFormat>,
+    // 已解码、统一为 \n 换行的frontmatter文本
+    pub frontmatter: String,
+    bom_len: usize,
+    // 文件使用的换行符，以第一行为准
+    line_ending: &'static str,
+    // 原有的起始和结束分隔符行（不含换行符），如以 ... 结束的YAML、带行尾空白的分隔符，写回时保持不变
+    opening_line: String,
+    closing_line: String,
+    // frontmatter最后一行（结束分隔符）原有的换行符，文件在此结束时为空
+    closing_line_ending: &'static str,
+    // 不兼容ASCII的编码（如UTF-16）先转为UTF-8再扫描，此时 body_start 是解码后的位置
+    transcoded: bool,
+    body_start: usize,
+    // 正文之前的行数
+    head_lines: usize,
+}
+
+// 拆分出行尾的换行符
+fn split_line_ending(line: &[u8]) -> (&[u8], &'static str) {
+    if let Some(line) = line.strip_suffix(b"\r\n") {
+        (line, "\r\n")
+    } else if let Some(line) = line.strip_suffix(b"\n") {
+        (line, "\n")
+    } else {
+        (line, "")
+    }
+}
+
+// 从文件开头扫描出的frontmatter，len 为扫描过的字节数
+struct Head {
+    format: Option<FrontmatterFormat>,
+    frontmatter: String,
+    line_ending: &'static str,
+    opening_line: String,
+    closing_line: String,
+    closing_line_ending: &'static str,
+    len: usize,
+    lines: usize,
+}
+
+// 逐行扫描，只读取到frontmatter结束为止
+fn scan_head(mut reader: impl BufRead, scan_encoding: &'static Encoding) -> Result<Head, Error> {
+    let mut scanner = FrontmatterScanner::default();
+    let mut frontmatter = String::new();
+    let mut line_ending = None;
+    let mut opening_line = String::new();
+    let mut closing_line = String::new();
+    let mut closing_line_ending = "";
+    let mut len = 0;
+    let mut lines = 0;
+    let mut raw_line = Vec::new();
+
+    while reader.read_until(b'\n', &mut raw_line)? > 0 {
+        let (line, ending) = split_line_ending(&raw_line);
+        line_ending.get_or_insert(ending);
+
+        let line = match scan_encoding.decode_without_bom_handling_and_without_replacement(line) {
+            Some(line) => line,
+            None if len == 0 => break, // 第一行无法解码，不可能是frontmatter
+            None => {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Frontmatter is not valid {}", scan_encoding.name()),
+                ));
+            }
+        };
+
+        match scanner.feed(&line) {
+            LineRole::Body => break,
+            LineRole::Frontmatter => {
+                frontmatter.push_str(&line);
+                frontmatter.push('\n');
+            }
+            LineRole::Delimiter if lines == 0 => opening_line = line.into_owned(),
+            LineRole::Delimiter => closing_line = line.into_owned(),
+        }
+        len += raw_line.len();
+        lines += 1;
+
+        if scanner.is_closed() {
+            closing_line_ending = ending;
+            break;
+        }
+        raw_line.clear();
+    }
+
+    Ok(Head {
+        format: scanner.format(),
+        frontmatter,
+        line_ending: line_ending
+            .filter(|ending| !ending.is_empty())
+            .unwrap_or("\n"),
+        opening_line,
+        closing_line,
+        closing_line_ending,
+        len,
+        lines,
+    })
+}
+
+// 解码一块内容追加到 dst，last 表示输入已经结束
+fn decode_chunk(decoder: &mut Decoder, mut src: &[u8], last: bool, dst: &mut String) {
+    loop {
+        dst.reserve(
+            decoder
+                .max_utf8_buffer_length(src.len())
+                .unwrap_or(src.len()),
+        );
+        let (result, read, _) = decoder.decode_to_string(src, dst, last);
+        src = &src[read..];
+        if matches!(result, CoderResult::InputEmpty) {
+            return;
+        }
+    }
+}
+
+// 按块解码为UTF-8的内容，用于按行扫描UTF-16等不兼容ASCII的编码；inner 从BOM之后开始
+struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    buffer: Vec<u8>,
+    decoded: String,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            buffer: vec![0; STREAM_BUFFER_SIZE],
+            decoded: String::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.position == self.decoded.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            let len = self.inner.read(&mut self.buffer)?;
+            self.finished = len == 0;
+            self.decoded.clear();
+            self.position = 0;
+            decode_chunk(
+                &mut self.decoder,
+                &self.buffer[..len],
+                self.finished,
+                &mut self.decoded,
+            );
+        }
+        let decoded = &self.decoded.as_bytes()[self.position..];
+        let len = decoded.len().min(buf.len());
+        buf[..len].copy_from_slice(&decoded[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Document {
+    pub fn read(file_path: &str) -> Result<Self, Error> {
+        Self::parse(fs::read(file_path)?)
+    }
+
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, Error> {
+        let (encoding, bom_len) = detect_encoding(&bytes);
+
+        // UTF-16 等编码中换行符不是单字节，先整体转为UTF-8再扫描
+        let transcoded = !encoding.is_ascii_compatible();
+        let head = if transcoded {
+            let text = encoding.decode_without_bom_handling(&bytes[bom_len..]).0;
+            scan_head(text.as_bytes(), UTF_8)?
+        } else {
+            scan_head(&bytes[bom_len..], encoding)?
+        };
+        Ok(Self::from_head(encoding, bom_len, head))
+    }
+
+    // 只读取文件开头的frontmatter，正文留在文件中，由 write_body 按块复制
+    // 编码已由调用方按块检测；UTF-16 等不兼容ASCII的编码边读边解码
+    pub fn read_head(
+        reader: &mut (impl Read + Seek),
+        encoding: &'static Encoding,
+        bom_len: usize,
+    ) -> Result<Self, Error> {
+        reader.seek(SeekFrom::Start(bom_len as u64))?;
+        let head = if encoding.is_ascii_compatible() {
+            scan_head(BufReader::new(&mut *reader), encoding)?
+        } else {
+            scan_head(
+                BufReader::new(DecodeReader::new(&mut *reader, encoding)),
+                UTF_8,
+            )?
+        };
+        Ok(Self::from_head(encoding, bom_len, head))
+    }
+
+    fn from_head(encoding: &'static Encoding, bom_len: usize, head: Head) -> Self {
+        let Head {
+            format,
+            mut frontmatter,
+            line_ending,
+            mut opening_line,
+            mut closing_line,
+            mut closing_line_ending,
+            len,
+            lines,
+        } = head;
+
+        // JSON对象无法解析时，开头的 { 多半属于正文，整个文件视为没有frontmatter
+        let format = format.filter(|&format| {
+            format != FrontmatterFormat::Json || parse_json_frontmatter(&frontmatter).is_ok()
+        });
+        // 转为UTF-8扫描时，位置从解码后的内容开头算起
+        let transcoded = !encoding.is_ascii_compatible();
+        let content_start = if transcoded { 0 } else { bom_len };
+        // 没有完整的frontmatter时，整个文件都是正文
+        let (body_start, head_lines) = if format.is_some() {
+            (content_start + len, lines)
+        } else {
+            frontmatter.clear();
+            opening_line.clear();
+            closing_line.clear();
+            closing_line_ending = line_ending;
+            (content_start, 0)
+        };
+
+        Self {
+            encoding,
+            format,
+            frontmatter,
+            bom_len,
+            line_ending,
+            opening_line,
+            closing_line,
+            closing_line_ending,
+            transcoded,
+            body_start,
+            head_lines,
+        }
+    }
+
+    // 正文可以从原文件逐字节复制，不需要重新编码
+    fn keeps_body(&self, target_encoding: &'static Encoding) -> bool {
+        target_encoding == self.encoding && !self.transcoded
+    }
+
+    // 新文件中正文之前的部分：BOM、分隔符和frontmatter，使用原有的换行符
+    // 格式不变时沿用原有的分隔符行，转换格式或插入新的frontmatter时使用标准的分隔符
+    pub fn render_header(
+        &self,
+        format: FrontmatterFormat,
+        frontmatter_content: &str,
+        target_encoding: &'static Encoding,
+    ) -> Result<Vec<u8>, Error> {
+        let mut header = String::with_capacity(frontmatter_content.len() * 2 + 16);
+        let content = frontmatter_content.replace('\n', self.line_ending);
+        let same_format = self.format == Some(format);
+        match format.delimiter() {
+            Some(delimiter) => {
+                let (opening, closing) = if same_format {
+                    (self.opening_line.as_str(), self.closing_line.as_str())
+                } else {
+                    (delimiter, delimiter)
+                };
+                header.push_str(opening);
+                header.push_str(self.line_ending);
+                header.push_str(&content);
+                header.push_str(closing);
+            }
+            // Pelican元数据之后的空行由 closing_line_ending 补上
+            None if format == FrontmatterFormat::Pelican => {
+                header.push_str(&content);
+                if same_format {
+                    header.push_str(&self.closing_line);
+                }
+            }
+            // JSON格式没有分隔符
+            None => header.push_str(content.strip_suffix(self.line_ending).unwrap_or(&content)),
+        }
+        header.push_str(self.closing_line_ending);
+
+        // 转为UTF-8时不保留其他编码的BOM
+        let mut output = Vec::with_capacity(header.len() + 4);
+        if self.bom_len > 0 && target_encoding == self.encoding {
+            output.extend_from_slice(bom_of(target_encoding));
+        }
+        output.extend_from_slice(&encode(&header, target_encoding)?);
+        Ok(output)
+    }
+
+    // 从头按块读取原文件：全部内容写入 original_sink（如计算哈希、写入记录），正文写入 output
+    // 目标编码与原编码相同时正文逐字节复制，否则逐块解码后重新编码，内存占用与文件大小无关
+    pub fn write_body(
+        &self,
+        original: &mut (impl Read + Seek),
+        target_encoding: &'static Encoding,
+        original_sink: &mut impl Write,
+        output: &mut impl Write,
+    ) -> Result<(), Error> {
+        original.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, original);
+        // 转为UTF-8扫描的文件先跳过BOM，解码后再跳过正文之前的部分
+        let (raw_skip, mut decoded_skip) = if self.transcoded {
+            (self.bom_len, self.body_start)
+        } else {
+            (self.body_start, 0)
+        };
+        let mut decoder = (!self.keeps_body(target_encoding))
+            .then(|| self.encoding.new_decoder_without_bom_handling());
+        let mut text = String::new();
+        let mut offset = 0;
+        loop {
+            let chunk = reader.fill_buf()?;
+            let len = chunk.len();
+            original_sink.write_all(chunk)?;
+            let body = &chunk[raw_skip.saturating_sub(offset).min(len)..];
+            match &mut decoder {
+                None => output.write_all(body)?,
+                Some(decoder) => {
+                    text.clear();
+                    decode_chunk(decoder, body, len == 0, &mut text);
+                    // 正文从行首开始，跳过的部分总是完整的字符
+                    let skip = decoded_skip.min(text.len());
+                    decoded_skip -= skip;
+                    output.write_all(&encode(&text[skip..], target_encoding)?)?;
+                }
+            }
+            if len == 0 {
+                return Ok(());
+            }
+            offset += len;
+            reader.consume(len);
+        }
+    }
+
+    // 解码后的前 lines 行，原有的frontmatter之后再多读取 context 行正文，用于预览修改
+    // 返回原有的开头和之后的正文
+    pub fn read_leading_text(
+        &self,
+        original: &mut (impl Read + Seek),
+        context: usize,
+    ) -> Result<(String, String), Error> {
+        original.seek(SeekFrom::Start(self.bom_len as u64))?;
+        let mut reader: Box<dyn BufRead + '_> = if self.transcoded {
+            Box::new(BufReader::new(DecodeReader::new(original, self.encoding)))
+        } else {
+            Box::new(BufReader::new(original))
+        };
+        let scan_encoding = if self.transcoded {
+            UTF_8
+        } else {
+            self.encoding
+        };
+
+        let mut head = String::new();
+        let mut body = String::new();
+        let mut raw_line = Vec::new();
+        for line in 0..self.head_lines + context {
+            raw_line.clear();
+            if reader.read_until(b'\n', &mut raw_line)? == 0 {
+                break;
+            }
+            let text = scan_encoding.decode_without_bom_handling(&raw_line).0;
+            if line < self.head_lines {
+                head.push_str(&text);
+            } else {
+                body.push_str(&text);
+            }
+        }
+        Ok((head, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, sink};
+
+    // 用原有的frontmatter文本重新生成整个文件
+    fn rewrite(original: &str, format: FrontmatterFormat, frontmatter: Option<&str>) -> String {
+        let document = Document::parse(original.as_bytes().to_vec()).unwrap();
+        let frontmatter = frontmatter.unwrap_or(&document.frontmatter);
+        let mut output = document.render_header(format, frontmatter, UTF_8).unwrap();
+        document
+            .write_body(
+                &mut Cursor::new(original.as_bytes()),
+                UTF_8,
+                &mut sink(),
+                &mut output,
+            )
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn delimiter_lines_are_kept() {
+        for (original, format) in [
+            ("---\ntitle: a\n...\nBody\n", FrontmatterFormat::Yaml),
+            (
+                "---  \r\ntitle: a\r\n---\t\r\nBody\r\n",
+                FrontmatterFormat::Yaml,
+            ),
+            ("+++\ntitle = 'a'\n+++", FrontmatterFormat::Toml),
+            ("Title: A\n  \nBody\n", FrontmatterFormat::Pelican),
+            ("{\n  \"title\": \"a\"\n}\nBody\n", FrontmatterFormat::Json),
+        ] {
+            assert_eq!(rewrite(original, format, None), original);
+        }
+        assert_eq!(
+            rewrite(
+                "---\ntitle: a\n...\nBody\n",
+                FrontmatterFormat::Yaml,
+                Some("title: b\n")
+            ),
+            "---\ntitle: b\n...\nBody\n"
+        );
+    }
+
+    // 转换格式或插入新的frontmatter时使用标准的分隔符
+    #[test]
+    fn new_formats_use_standard_delimiters() {
+        assert_eq!(
+            rewrite(
+                "Title: A\n  \nBody\n",
+                FrontmatterFormat::Yaml,
+                Some("title: A\n")
+            ),
+            "---\ntitle: A\n---\nBody\n"
+        );
+        assert_eq!(
+            rewrite("Body\r\n", FrontmatterFormat::Toml, Some("title = 'a'\n")),
+            "+++\r\ntitle = 'a'\r\n+++\r\nBody\r\n"
+        );
+    }
+}
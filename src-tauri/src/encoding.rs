@@ -0,0 +1,176 @@
+use crate::constants::*;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use std::{
+    borrow::Cow,
+    io::{Error, Read, Seek, SeekFrom, Write},
+};
+
+// 检测文件编码，返回编码和BOM的长度
+// 优先使用BOM，其次尝试UTF-8，都不符合时交给 chardetng 猜测（如GBK、Big5）
+pub fn detect_encoding(bytes: &[u8]) -> (&'static Encoding, usize) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return (encoding, bom_len);
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, 0);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    (detector.guess(None, true), 0)
+}
+
+// 按块读取文件并检测编码，结果与对整个文件调用 detect_encoding 相同，不把文件读入内存
+// 读到的内容依次写入 sink（如计算哈希）；需要 chardetng 猜测时会再读一遍，不重复写入
+pub fn detect_encoding_streamed(
+    reader: &mut (impl Read + Seek),
+    sink: &mut impl Write,
+) -> Result<(&'static Encoding, usize), Error> {
+    let mut buffer = vec![0; STREAM_BUFFER_SIZE];
+    let mut prefix = Vec::with_capacity(3);
+    // 上一块末尾被截断的UTF-8字符
+    let mut pending = Vec::with_capacity(STREAM_BUFFER_SIZE + 3);
+    let mut is_utf8 = true;
+    reader.seek(SeekFrom::Start(0))?;
+    loop {
+        let len = reader.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        let chunk = &buffer[..len];
+        sink.write_all(chunk)?;
+        if prefix.len() < 3 {
+            prefix.extend(chunk.iter().take(3 - prefix.len()));
+        }
+        if is_utf8 {
+            pending.extend_from_slice(chunk);
+            match std::str::from_utf8(&pending) {
+                Ok(_) => pending.clear(),
+                Err(e) if e.error_len().is_none() => {
+                    pending.drain(..e.valid_up_to());
+                }
+                Err(_) => is_utf8 = false,
+            }
+        }
+    }
+
+    if let Some((encoding, bom_len)) = Encoding::for_bom(&prefix) {
+        return Ok((encoding, bom_len));
+    }
+    if is_utf8 && pending.is_empty() {
+        return Ok((UTF_8, 0));
+    }
+
+    let mut detector = EncodingDetector::new();
+    reader.seek(SeekFrom::Start(0))?;
+    loop {
+        let len = reader.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        detector.feed(&buffer[..len], false);
+    }
+    detector.feed(&[], true);
+    Ok((detector.guess(None, true), 0))
+}
+
+pub fn bom_of(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_16LE {
+        b"\xff\xfe"
+    } else if encoding == UTF_16BE {
+        b"\xfe\xff"
+    } else {
+        b"\xef\xbb\xbf"
+    }
+}
+
+// 按指定编码编码文本，遇到该编码无法表示的字符时返回错误而不是写入替代字符
+pub fn encode<'a>(text: &'a str, encoding: &'static Encoding) -> Result<Cow<'a, [u8]>, Error> {
+    // encoding_rs 不支持输出UTF-16，需要单独处理
+    if encoding == UTF_16LE {
+        return Ok(Cow::Owned(
+            text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        ));
+    }
+    if encoding == UTF_16BE {
+        return Ok(Cow::Owned(
+            text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        ));
+    }
+
+    let (bytes, _, had_errors) = encoding.encode(text);
+    if had_errors {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Text contains characters that cannot be encoded as {}",
+                encoding.name()
+            ),
+        ));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{BIG5, GBK, SHIFT_JIS};
+    use std::io::Cursor;
+
+    fn with_bom(encoding: &'static Encoding, text: &str) -> Vec<u8> {
+        let mut bytes = bom_of(encoding).to_vec();
+        bytes.extend_from_slice(&encode(text, encoding).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn encodings_are_detected_the_same_when_streamed() {
+        let text =
+            "---\ntitle: 中文标题\n---\n\n这是一段用于检测编码的中文正文，内容需要足够长。\n";
+        let traditional =
+            "---\ntitle: 繁體標題\n---\n\n這是一段用於檢測編碼的繁體中文正文，內容需要足夠長。\n";
+        let japanese = "---\ntitle: 日本語のタイトル\n---\n\nこれは文字コードを判定するための日本語の本文です。\n";
+        for (bytes, encoding, bom_len) in [
+            (text.as_bytes().to_vec(), UTF_8, 0),
+            (with_bom(UTF_8, text), UTF_8, 3),
+            (with_bom(UTF_16LE, text), UTF_16LE, 2),
+            (with_bom(UTF_16BE, text), UTF_16BE, 2),
+            (encode(text, GBK).unwrap().into_owned(), GBK, 0),
+            (encode(traditional, BIG5).unwrap().into_owned(), BIG5, 0),
+            (
+                encode(japanese, SHIFT_JIS).unwrap().into_owned(),
+                SHIFT_JIS,
+                0,
+            ),
+        ] {
+            assert_eq!(
+                detect_encoding(&bytes),
+                (encoding, bom_len),
+                "{}",
+                encoding.name()
+            );
+
+            let mut sink = Vec::new();
+            let streamed = detect_encoding_streamed(&mut Cursor::new(&bytes), &mut sink).unwrap();
+            assert_eq!(streamed, (encoding, bom_len), "{}", encoding.name());
+            assert_eq!(sink, bytes, "{}", encoding.name());
+        }
+    }
+
+    // 跨越读取块边界的多字节UTF-8字符不会被误判为其他编码
+    #[test]
+    fn utf8_split_across_chunks_is_still_utf8() {
+        let bytes = format!("{}中文", "a".repeat(STREAM_BUFFER_SIZE - 1)).into_bytes();
+        let streamed = detect_encoding_streamed(&mut Cursor::new(&bytes), &mut std::io::sink());
+        assert_eq!(streamed.unwrap(), (UTF_8, 0));
+    }
+
+    #[test]
+    fn unencodable_text_is_an_error() {
+        assert_eq!(encode("中文", GBK).unwrap().as_ref(), b"\xd6\xd0\xce\xc4");
+        assert!(encode("emoji 😀", GBK).is_err());
+        assert_eq!(encode("A", UTF_16LE).unwrap().as_ref(), b"A\0");
+        assert_eq!(encode("A", UTF_16BE).unwrap().as_ref(), b"\0A");
+    }
+}
@@ -0,0 +1,244 @@
+use crate::constants::*;
+use serde::{Deserialize, Serialize};
+
+// frontmatter 的格式，由起始分隔符决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontmatterFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+    // Pelican/MultiMarkdown 风格的 Key: value 元数据，以空行结束
+    Pelican,
+}
+
+impl FrontmatterFormat {
+    // 根据起始分隔符行判断格式，分隔符必须完整匹配（允许行尾空白），不是分隔符则返回 None
+    pub fn from_delimiter(line: &str) -> Option<Self> {
+        match line.trim_end() {
+            FRONTMATTER_DELIMITER => Some(Self::Yaml),
+            TOML_FRONTMATTER_DELIMITER => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    // YAML 允许使用文档结束标记 ... 作为结束分隔符
+    fn is_closing_delimiter(self, line: &str) -> bool {
+        let line = line.trim_end();
+        self.delimiter() == Some(line) || (self == Self::Yaml && line == YAML_DOCUMENT_END)
+    }
+
+    // JSON frontmatter 本身就是一个对象，Pelican元数据直接从第一行开始，都没有分隔符
+    pub fn delimiter(self) -> Option<&'static str> {
+        match self {
+            Self::Yaml => Some(FRONTMATTER_DELIMITER),
+            Self::Toml => Some(TOML_FRONTMATTER_DELIMITER),
+            Self::Json | Self::Pelican => None,
+        }
+    }
+}
+
+// JSON frontmatter 的第一行只有 {，或者 { 之后紧接着对象的第一个键
+// 以 {{< shortcode >}} 或 {% liquid %} 开头的正文不会被当作JSON
+pub fn is_json_frontmatter_start(line: &str) -> bool {
+    line.trim()
+        .strip_prefix(JSON_FRONTMATTER_START)
+        .is_some_and(|rest| rest.is_empty() || rest.trim_start().starts_with('"'))
+}
+
+// Pelican元数据的键只能包含字母、数字、下划线和连字符
+pub fn is_metadata_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// 拆分 Key: value 行，键必须从第0列开始
+pub fn split_metadata_line(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    is_metadata_key(key).then(|| (key, value.trim()))
+}
+
+// 缩进至少4个空格或以制表符开始的行是上一个值的续行
+pub fn is_metadata_continuation(line: &str) -> bool {
+    (line.starts_with(PELICAN_CONTINUATION_INDENT) || line.starts_with('\t'))
+        && !line.trim().is_empty()
+}
+
+// Pelican元数据中的一项，值的续行以换行连接
+pub struct MetadataEntry<'a> {
+    pub key: &'a str,
+    pub value: String,
+    // 包括续行在内的原始文本
+    pub text: &'a str,
+    // 键所在的行，从1开始
+    pub line: usize,
+}
+
+pub fn metadata_entries(content: &str) -> Vec<MetadataEntry<'_>> {
+    let mut entries: Vec<MetadataEntry> = Vec::new();
+    let mut entry_start = 0;
+    let mut offset = 0;
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_end_matches(['\r', '\n']);
+
+        if let Some((key, value)) = split_metadata_line(trimmed) {
+            entry_start = start;
+            entries.push(MetadataEntry {
+                key,
+                value: value.to_owned(),
+                text: &content[start..offset],
+                line: index + 1,
+            });
+        } else if is_metadata_continuation(trimmed)
+            && let Some(entry) = entries.last_mut()
+        {
+            entry.value.push('\n');
+            entry.value.push_str(trimmed.trim());
+            entry.text = &content[entry_start..offset];
+        }
+    }
+    entries
+}
+
+// 一行在文件中的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineRole {
+    Delimiter,
+    Frontmatter,
+    Body,
+}
+
+// 逐行扫描文件，判断每一行属于分隔符、frontmatter 还是正文
+// frontmatter 只能从文件第一行（可带BOM）开始，未闭合的视为没有 frontmatter
+#[derive(Default)]
+pub struct FrontmatterScanner {
+    format: Option<FrontmatterFormat>,
+    line_index: usize,
+    closed: bool,
+    json: JsonDepth,
+    // Pelican元数据必须包含 title，避免把以 Key: value 开头的普通正文误认为元数据
+    has_title: bool,
+}
+
+impl FrontmatterScanner {
+    pub fn feed(&mut self, line: &str) -> LineRole {
+        let line_index = self.line_index;
+        self.line_index += 1;
+
+        if self.closed {
+            return LineRole::Body;
+        }
+
+        match self.format {
+            Some(FrontmatterFormat::Json) => {
+                self.closed = self.json.feed(line);
+                LineRole::Frontmatter
+            }
+            Some(FrontmatterFormat::Pelican) => {
+                if line.trim().is_empty() {
+                    self.closed = true;
+                    LineRole::Delimiter
+                } else if self.feed_metadata(line) {
+                    LineRole::Frontmatter
+                } else {
+                    // 出现无法识别的行，整个文件视为没有frontmatter
+                    self.format = None;
+                    self.closed = true;
+                    LineRole::Body
+                }
+            }
+            Some(format) => {
+                if format.is_closing_delimiter(line) {
+                    self.closed = true;
+                    LineRole::Delimiter
+                } else {
+                    LineRole::Frontmatter
+                }
+            }
+            None if line_index == 0 => {
+                let line = line.strip_prefix(UTF8_BOM).unwrap_or(line);
+                if is_json_frontmatter_start(line) {
+                    self.format = Some(FrontmatterFormat::Json);
+                    self.closed = self.json.feed(line);
+                    LineRole::Frontmatter
+                } else if let Some(format) = FrontmatterFormat::from_delimiter(line) {
+                    self.format = Some(format);
+                    LineRole::Delimiter
+                } else if split_metadata_line(line).is_some() {
+                    self.format = Some(FrontmatterFormat::Pelican);
+                    self.feed_metadata(line);
+                    LineRole::Frontmatter
+                } else {
+                    LineRole::Body
+                }
+            }
+            None => LineRole::Body,
+        }
+    }
+
+    // 返回该行是否属于Pelican元数据
+    fn feed_metadata(&mut self, line: &str) -> bool {
+        if let Some((key, _)) = split_metadata_line(line) {
+            self.has_title |= key.eq_ignore_ascii_case(PELICAN_TITLE_KEY);
+            true
+        } else {
+            is_metadata_continuation(line)
+        }
+    }
+
+    // 只有完整闭合的 frontmatter 才会返回格式
+    pub fn format(&self) -> Option<FrontmatterFormat> {
+        self.format.filter(|format| {
+            self.closed && (*format != FrontmatterFormat::Pelican || self.has_title)
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+// 跟踪JSON对象的嵌套深度，忽略字符串中的括号
+#[derive(Default)]
+struct JsonDepth {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonDepth {
+    // 返回最外层对象是否已经闭合
+    fn feed(&mut self, line: &str) -> bool {
+        for c in line.chars() {
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == '\\' {
+                    self.escaped = true;
+                } else if c == '"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match c {
+                '"' => self.in_string = true,
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+}
@@ -0,0 +1,543 @@
+use crate::{
+    atomic_write::{StagedFile, write_atomic},
+    constants::*,
+    document::Document,
+    encoding::detect_encoding_streamed,
+    parse_frontmatter, read_and_parse_file,
+    watch::OwnWrites,
+    write::{WriteReport, WriteResult, WriteStatus, written_revision},
+};
+use serde::{Deserialize, Serialize};
+use serde_yaml_ng::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, TryLockError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use indexmap::IndexMap;
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashSet<T> = HashSet<T, ahash::RandomState>;
+type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
+
+// 文件在写入前后的内容的SHA-256，内容本身按哈希保存在 blobs 目录中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchState {
+    Applied,
+    Undone,
+}
+
+// 一次批量写入涉及的所有文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalBatch {
+    pub id: u64,
+    // 写入时间，自UNIX纪元起的毫秒数
+    pub created_at: u64,
+    pub state: BatchState,
+    pub files: AHashIndexMap<String, JournalEntry>,
+}
+
+// 同一进程中的写入、撤销和重做共享的锁，每次打开写入记录时复制一份
+#[derive(Default, Clone)]
+pub struct JournalLock {
+    // 分配批次编号、清理记录以及读取和修改批次时持有
+    batches: Arc<Mutex<()>>,
+    // 写入和恢复期间持有读锁，此时保存的内容可能还没有记录到批次中；清理时需要独占
+    active: Arc<RwLock<()>>,
+}
+
+// 正在进行的写入或恢复，持有期间不会清理写入记录
+pub type ActiveWrite<'a> = RwLockReadGuard<'a, ()>;
+
+// 保存在应用数据目录中的写入记录，用于撤销和重做批量写入
+pub struct Journal {
+    dir: PathBuf,
+    lock: JournalLock,
+}
+
+// 按块计算文件内容的SHA-256，文件不存在时返回 None
+fn sha256_of_file(file_path: &str) -> Result<Option<String>, Error> {
+    let mut file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+impl Journal {
+    pub fn open(app: &AppHandle) -> Result<Self, Error> {
+        let dir = app.path().app_data_dir().map_err(Error::other)?;
+        let lock = JournalLock::clone(&app.state::<JournalLock>());
+        Self::at(dir.join(JOURNAL_DIR), lock)
+    }
+
+    fn at(dir: PathBuf, lock: JournalLock) -> Result<Self, Error> {
+        fs::create_dir_all(dir.join(JOURNAL_BLOBS_DIR))?;
+        fs::create_dir_all(dir.join(JOURNAL_BATCHES_DIR))?;
+        Ok(Self { dir, lock })
+    }
+
+    // 开始写入前调用，保存的内容记录到批次之前不会被清理
+    pub fn begin(&self) -> ActiveWrite<'_> {
+        self.lock
+            .active
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_batches(&self) -> MutexGuard<'_, ()> {
+        self.lock
+            .batches
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(JOURNAL_BLOBS_DIR).join(sha256)
+    }
+
+    fn batch_path(&self, id: u64) -> PathBuf {
+        self.dir
+            .join(JOURNAL_BATCHES_DIR)
+            .join(format!("{}.json", id))
+    }
+
+    // 按块保存文件内容，写完后调用 BlobWriter::finish 得到其哈希
+    pub fn blob_writer(&self) -> Result<BlobWriter<'_>, Error> {
+        Ok(BlobWriter {
+            journal: self,
+            staged: StagedFile::create(&self.blob_path(JOURNAL_PENDING_BLOB))?,
+            hasher: Sha256::new(),
+        })
+    }
+
+    // 只读取内容开头的frontmatter，用于在冲突时展示要恢复的值
+    fn frontmatter_of(&self, sha256: &str) -> Result<AHashIndexMap<String, Value>, Error> {
+        let mut file = File::open(self.blob_path(sha256))?;
+        let (encoding, bom_len) = detect_encoding_streamed(&mut file, &mut io::sink())?;
+        let document = Document::read_head(&mut file, encoding, bom_len)?;
+        Ok(document
+            .format
+            .and_then(|format| parse_frontmatter(format, &document.frontmatter).ok())
+            .map(|(frontmatter, _)| frontmatter)
+            .unwrap_or_default())
+    }
+
+    // 按编号从新到旧排列，无法读取的记录会被忽略
+    fn batches(&self) -> Result<Vec<JournalBatch>, Error> {
+        let mut batches = Vec::new();
+        for entry in fs::read_dir(self.dir.join(JOURNAL_BATCHES_DIR))? {
+            let path = entry?.path();
+            let batch = fs::read(&path)
+                .and_then(|content| Ok(serde_json::from_slice::<JournalBatch>(&content)?));
+            match batch {
+                Ok(batch) => batches.push(batch),
+                Err(e) => eprintln!(
+                    "Ignoring unreadable journal batch {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        batches.sort_unstable_by_key(|batch| Reverse(batch.id));
+        Ok(batches)
+    }
+
+    fn read_batch(&self, id: u64) -> Result<JournalBatch, Error> {
+        let content = fs::read(self.batch_path(id))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    fn save_batch(&self, batch: &JournalBatch) -> Result<(), Error> {
+        write_atomic(&self.batch_path(batch.id), &serde_json::to_vec(batch)?)
+    }
+
+    // 记录一次批量写入，返回批次的编号；记录后结束这次写入，并清理旧的记录
+    pub fn record(
+        &self,
+        files: AHashIndexMap<String, JournalEntry>,
+        active: ActiveWrite<'_>,
+    ) -> Result<u64, Error> {
+        let id = {
+            let _batches = self.lock_batches();
+            let id = self.batches()?.first().map_or(1, |batch| batch.id + 1);
+            let created_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as u64);
+            self.save_batch(&JournalBatch {
+                id,
+                created_at,
+                state: BatchState::Applied,
+                files,
+            })?;
+            id
+        };
+        drop(active);
+        if let Err(e) = self.prune() {
+            eprintln!("Failed to prune write journal: {}", e);
+        }
+        Ok(id)
+    }
+
+    // 只保留最近的批次，并删除不再被任何批次引用的内容
+    // 其他写入正在进行时跳过，它们保存的内容还没有被引用，留给之后的记录清理
+    fn prune(&self) -> Result<(), Error> {
+        let _idle = match self.lock.active.try_write() {
+            Ok(idle) => idle,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Ok(()),
+        };
+        let _batches = self.lock_batches();
+        let mut batches = self.batches()?;
+        for batch in batches.split_off(batches.len().min(JOURNAL_MAX_BATCHES)) {
+            fs::remove_file(self.batch_path(batch.id))?;
+        }
+
+        let referenced: AHashHashSet<&str> = batches
+            .iter()
+            .flat_map(|batch| batch.files.values())
+            .flat_map(|entry| [entry.before.as_str(), entry.after.as_str()])
+            .collect();
+        for entry in fs::read_dir(self.dir.join(JOURNAL_BLOBS_DIR))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // 其他写入正在保存的内容
+            if name.ends_with(ATOMIC_TEMP_SUFFIX) {
+                continue;
+            }
+            if !referenced.contains(name.as_ref()) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    // 将文件从 source 版本恢复为 target 版本
+    // 文件已经不是 source 版本时说明批次之后又被修改过，作为冲突返回；force 时仍然覆盖
+    fn restore_file(
+        &self,
+        file_path: &str,
+        source: &str,
+        target: &str,
+        force: bool,
+        own_writes: &OwnWrites,
+    ) -> WriteResult {
+        self.try_restore_file(file_path, source, target, force, own_writes)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to restore file {}: {}", file_path, e);
+                WriteResult::error(WriteStatus::Failed, e.into())
+            })
+    }
+
+    fn try_restore_file(
+        &self,
+        file_path: &str,
+        source: &str,
+        target: &str,
+        force: bool,
+        own_writes: &OwnWrites,
+    ) -> Result<WriteResult, Error> {
+        let current_sha256 = sha256_of_file(file_path)?;
+        if current_sha256.as_deref() == Some(target) {
+            return Ok(WriteResult {
+                status: WriteStatus::Skipped,
+                error_kind: None,
+                message: Some("File is already in this state".to_string()),
+                revision: None,
+                conflict: None,
+            });
+        }
+
+        if current_sha256.as_deref() != Some(source) && !force {
+            return Ok(WriteResult::conflict(
+                self.frontmatter_of(target)?,
+                read_and_parse_file(file_path),
+            ));
+        }
+
+        // 按块从保存的内容复制到临时文件，再替换原文件
+        let mut content = File::open(self.blob_path(target))?;
+        let mut staged = StagedFile::create(Path::new(file_path))?;
+        io::copy(&mut content, &mut staged)?;
+        own_writes.expect(file_path, target);
+        staged.commit()?;
+        Ok(WriteResult::written(written_revision(
+            file_path,
+            target.to_owned(),
+        )))
+    }
+}
+
+// 正在保存到写入记录中的内容，边写入边计算哈希，完成时以哈希为文件名保存，相同的内容只保存一份
+pub struct BlobWriter<'a> {
+    journal: &'a Journal,
+    staged: StagedFile,
+    hasher: Sha256,
+}
+
+impl BlobWriter<'_> {
+    pub fn finish(self) -> Result<String, Error> {
+        let sha256 = format!("{:x}", self.hasher.finalize());
+        let path = self.journal.blob_path(&sha256);
+        // 已有相同内容时丢弃临时文件
+        if !path.exists() {
+            self.staged.commit_to(&path)?;
+        }
+        Ok(sha256)
+    }
+}
+
+impl Write for BlobWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let len = self.staged.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.staged.flush()
+    }
+}
+
+fn journal_error(e: Error) -> String {
+    format!("{}{}", ERROR_JOURNAL, e)
+}
+
+// 撤销时恢复为写入前的内容，重做时恢复为写入后的内容
+// 恢复期间持有批次的锁，同一批次不会被同时撤销和重做，也不会被清理
+fn apply_batch(
+    journal: &Journal,
+    own_writes: &OwnWrites,
+    batch_id: u64,
+    undo: bool,
+    force: bool,
+) -> Result<WriteReport, String> {
+    let _active = journal.begin();
+    let _batches = journal.lock_batches();
+    let mut batch = journal.read_batch(batch_id).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => format!("{}{}", ERROR_BATCH_NOT_FOUND, batch_id),
+        _ => journal_error(e),
+    })?;
+
+    let results: Vec<(String, WriteResult)> = batch
+        .files
+        .par_iter()
+        .map(|(file_path, entry)| {
+            let (source, target) = if undo {
+                (&entry.after, &entry.before)
+            } else {
+                (&entry.before, &entry.after)
+            };
+            let result = journal.restore_file(file_path, source, target, force, own_writes);
+            (file_path.clone(), result)
+        })
+        .collect();
+    let report = WriteReport::from_results(results);
+
+    // 所有文件都已恢复时才更新状态，有冲突或失败的文件时可以处理后再次执行
+    if report.summary.conflict == 0 && report.summary.failed == 0 {
+        batch.state = if undo {
+            BatchState::Undone
+        } else {
+            BatchState::Applied
+        };
+        journal.save_batch(&batch).map_err(journal_error)?;
+    }
+    Ok(report)
+}
+
+// 列出最近的批量写入，从新到旧排列
+#[tauri::command]
+pub fn list_write_batches(app: AppHandle) -> Result<Vec<JournalBatch>, String> {
+    Journal::open(&app)
+        .and_then(|journal| {
+            let _batches = journal.lock_batches();
+            journal.batches()
+        })
+        .map_err(journal_error)
+}
+
+// 撤销一次批量写入，force 时覆盖批次之后又被修改过的文件
+#[tauri::command]
+pub fn undo_write_batch(
+    app: AppHandle,
+    batch_id: u64,
+    force: Option<bool>,
+) -> Result<WriteReport, String> {
+    let journal = Journal::open(&app).map_err(journal_error)?;
+    let own_writes = app.state::<OwnWrites>();
+    apply_batch(
+        &journal,
+        &own_writes,
+        batch_id,
+        true,
+        force.unwrap_or(false),
+    )
+}
+
+// 重新应用一次已撤销的批量写入
+#[tauri::command]
+pub fn redo_write_batch(
+    app: AppHandle,
+    batch_id: u64,
+    force: Option<bool>,
+) -> Result<WriteReport, String> {
+    let journal = Journal::open(&app).map_err(journal_error)?;
+    let own_writes = app.state::<OwnWrites>();
+    apply_batch(
+        &journal,
+        &own_writes,
+        batch_id,
+        false,
+        force.unwrap_or(false),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = "---\ntitle: Old\n---\n\nBody\n";
+    const AFTER: &str = "---\ntitle: New\n---\n\nBody\n";
+
+    fn journal(name: &str) -> Journal {
+        let dir = std::env::temp_dir().join(format!("cyrene-test-journal-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        Journal::at(dir, JournalLock::default()).unwrap()
+    }
+
+    fn save(journal: &Journal, content: &str) -> String {
+        let mut writer = journal.blob_writer().unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+        writer.finish().unwrap()
+    }
+
+    // 模拟一次写入：替换文件并记录前后的内容
+    fn write_batch(journal: &Journal, file_path: &str, before: &str, after: &str) -> u64 {
+        let active = journal.begin();
+        fs::write(file_path, after).unwrap();
+        let entry = JournalEntry {
+            before: save(journal, before),
+            after: save(journal, after),
+        };
+        let files = AHashIndexMap::from_iter([(file_path.to_owned(), entry)]);
+        journal.record(files, active).unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_recorded_content() {
+        let journal = journal("undo");
+        let path = journal.dir.join("post.md");
+        let file_path = path.to_str().unwrap();
+        let id = write_batch(&journal, file_path, BEFORE, AFTER);
+
+        let report = apply_batch(&journal, &OwnWrites::default(), id, true, false).unwrap();
+        assert_eq!(report.summary.written, 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), BEFORE);
+        assert_eq!(journal.read_batch(id).unwrap().state, BatchState::Undone);
+        assert_eq!(
+            report.files[file_path].revision.as_ref().unwrap().sha256,
+            sha256_of_file(file_path).unwrap()
+        );
+
+        // 已经是目标内容的文件不再写入
+        let report = apply_batch(&journal, &OwnWrites::default(), id, true, false).unwrap();
+        assert_eq!(report.summary.skipped, 1);
+
+        let report = apply_batch(&journal, &OwnWrites::default(), id, false, false).unwrap();
+        assert_eq!(report.summary.written, 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), AFTER);
+        assert_eq!(journal.read_batch(id).unwrap().state, BatchState::Applied);
+    }
+
+    #[test]
+    fn files_changed_after_the_batch_are_conflicts() {
+        let journal = journal("conflict");
+        let path = journal.dir.join("post.md");
+        let file_path = path.to_str().unwrap();
+        let id = write_batch(&journal, file_path, BEFORE, AFTER);
+        let edited = "---\ntitle: Edited\n---\n\nBody\n";
+        fs::write(&path, edited).unwrap();
+
+        let report = apply_batch(&journal, &OwnWrites::default(), id, true, false).unwrap();
+        assert_eq!(report.summary.conflict, 1);
+        assert_eq!(report.files[file_path].status, WriteStatus::Conflict);
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+        assert_eq!(journal.read_batch(id).unwrap().state, BatchState::Applied);
+
+        // 删除的文件同样视为冲突
+        fs::remove_file(&path).unwrap();
+        let report = apply_batch(&journal, &OwnWrites::default(), id, true, false).unwrap();
+        assert_eq!(report.summary.conflict, 1);
+        assert!(!path.exists());
+
+        let report = apply_batch(&journal, &OwnWrites::default(), id, true, true).unwrap();
+        assert_eq!(report.summary.written, 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), BEFORE);
+        assert_eq!(journal.read_batch(id).unwrap().state, BatchState::Undone);
+    }
+
+    #[test]
+    fn concurrent_batches_get_distinct_ids() {
+        let journal = journal("ids");
+        let mut ids: Vec<u64> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let journal = &journal;
+                    scope.spawn(move || {
+                        let path = journal.dir.join(format!("post-{}.md", i));
+                        let after = format!("{}{}", AFTER, i);
+                        write_batch(journal, path.to_str().unwrap(), BEFORE, &after)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        ids.sort_unstable();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn content_of_running_writes_is_not_pruned() {
+        let journal = journal("prune");
+        let path = journal.dir.join("post.md");
+        let file_path = path.to_str().unwrap();
+
+        // 另一个写入已经保存了内容，但还没有记录批次
+        let running = journal.begin();
+        let pending = save(&journal, "Unrecorded\n");
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| write_batch(&journal, file_path, BEFORE, AFTER))
+                .join()
+                .unwrap()
+        });
+        assert!(journal.blob_path(&pending).exists());
+
+        drop(running);
+        write_batch(&journal, file_path, AFTER, BEFORE);
+        assert!(!journal.blob_path(&pending).exists());
+        let recorded = format!("{:x}", Sha256::digest(AFTER));
+        assert!(journal.blob_path(&recorded).exists());
+    }
+}
@@ -1,106 +1,289 @@
+mod atomic_write;
 mod backup;
+mod cache;
+mod config;
 mod constants;
+mod datetime;
+mod document;
+mod encoding;
+mod format;
+mod journal;
+mod preview;
+mod revision;
+mod scan;
+mod stream;
+mod toml_patch;
+mod watch;
 mod write;
 mod xlsx;
+mod yaml_emit;
+mod yaml_patch;
 
-use crate::constants::*;
-use std::{
-    fs::File,
-    io::{BufRead, BufReader, Error},
+use crate::{
+    cache::ParseCache,
+    config::has_content_extension,
+    datetime::{DateTimeValue, tag_datetimes},
+    document::Document,
+    format::{FrontmatterFormat, metadata_entries},
+    revision::FileRevision,
+    yaml_patch::resolve_merge_keys,
 };
+use std::{fs, io::Error, path::Path};
 
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use serde_yaml_ng::Value;
-use walkdir::WalkDir;
+use tauri::{AppHandle, Manager, RunEvent, State};
 
 use indexmap::IndexMap;
 
 // 使用ahash作为哈希器的类型别名
 type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
 
-fn read_frontmatter(file_path: &str) -> Result<String, Error> {
-    // todo:remove unwrap
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-
-    let mut frontmatter_started = false;
-    let mut res = String::new();
-    for line_res in reader.lines() {
-        let line = line_res?;
-        if line.starts_with(FRONTMATTER_DELIMITER) {
-            if frontmatter_started {
-                break; // End of frontmatter
-            } else {
-                frontmatter_started = true; // Start of frontmatter
-                continue;
-            }
-        } else if frontmatter_started {
-            res.push_str(&line);
-            res.push('\n');
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ParseErrorKind {
+    Io,
+    Encoding,
+    Syntax,
+}
+
+// 单个文件的解析错误，行列号从1开始，相对于整个文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ParseError {
+    kind: ParseErrorKind,
+    message: String,
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+impl ParseError {
+    fn syntax(message: impl ToString, position: Option<(usize, usize)>) -> Self {
+        Self {
+            kind: ParseErrorKind::Syntax,
+            message: message.to_string(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
         }
     }
 
-    Ok(res)
+    // 将frontmatter内部的行号换算为文件中的行号
+    fn offset_lines(mut self, lines_before: usize) -> Self {
+        self.line = self.line.map(|line| line + lines_before);
+        self
+    }
 }
 
-fn parse_yaml_frontmatter(content: &str) -> Result<AHashIndexMap<String, Value>, Error> {
-    let data = serde_yaml_ng::from_str::<AHashIndexMap<String, Value>>(content);
-    match data {
-        Ok(parsed) => Ok(parsed),
-        Err(e) => {
-            eprintln!("Error parsing YAML frontmatter: {}", e);
-            Err(Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to parse YAML frontmatter",
-            ))
+impl From<Error> for ParseError {
+    fn from(e: Error) -> Self {
+        let kind = match e.kind() {
+            std::io::ErrorKind::InvalidData => ParseErrorKind::Encoding,
+            _ => ParseErrorKind::Io,
+        };
+        Self {
+            kind,
+            message: e.to_string(),
+            line: None,
+            column: None,
         }
     }
 }
 
-#[tauri::command]
-fn read_and_parse_yaml_frontmatter(file_path: &str) -> AHashIndexMap<String, Value> {
-    let content = read_frontmatter(file_path);
+// 单个文件的读取结果，format 为 null 表示文件没有frontmatter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ParsedFile {
+    frontmatter: AHashIndexMap<String, Value>,
+    format: Option<FrontmatterFormat>,
+    // 检测到的文件编码，如 UTF-8、GBK、Big5
+    encoding: Option<String>,
+    // 通过YAML合并键 << 继承、没有在当前文件中显式写出的键
+    inherited: Vec<String>,
+    // 读取时的文件版本，无法读取文件时为空
+    revision: FileRevision,
+    error: Option<ParseError>,
+}
 
-    match content {
-        Ok(content) => {
-            parse_yaml_frontmatter(&content).unwrap_or_else(|_| AHashIndexMap::default())
+// 返回展开合并键之后的值，以及继承得到的键
+fn parse_yaml_frontmatter(
+    content: &str,
+) -> Result<(AHashIndexMap<String, Value>, Vec<String>), ParseError> {
+    let raw = serde_yaml_ng::from_str::<AHashIndexMap<String, Value>>(content).or_else(|e| {
+        // 只有空白或注释的frontmatter视为空
+        if serde_yaml_ng::from_str::<Value>(content).is_ok_and(|value| value.is_null()) {
+            return Ok(AHashIndexMap::default());
         }
+        let position = e
+            .location()
+            .map(|location| (location.line(), location.column()));
+        Err(ParseError::syntax(e, position))
+    })?;
+    resolve_merge_keys(&raw).map_err(|e| ParseError::syntax(e, None))
+}
+
+fn parse_toml_frontmatter(content: &str) -> Result<AHashIndexMap<String, Value>, ParseError> {
+    match toml::from_str::<toml::Table>(content) {
+        Ok(table) => Ok(table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_yaml_value(value)))
+            .collect()),
         Err(e) => {
-            eprintln!("Error reading frontmatter: {}", e);
-            AHashIndexMap::default() // Return an empty map on error
+            let position = e.span().map(|span| line_column_at(content, span.start));
+            Err(ParseError::syntax(e.message(), position))
         }
     }
 }
 
-#[tauri::command]
-fn read_and_parse_multiple_frontmatter(
-    file_paths: Vec<&str>,
-) -> AHashIndexMap<String, AHashIndexMap<String, Value>> {
-    file_paths
-        .par_iter()
-        .map(|&file_path| {
-            let frontmatter = read_and_parse_yaml_frontmatter(file_path);
-            (file_path.to_owned(), frontmatter)
-        })
-        .collect()
+fn parse_json_frontmatter(content: &str) -> Result<AHashIndexMap<String, Value>, ParseError> {
+    serde_json::from_str::<AHashIndexMap<String, Value>>(content)
+        .map_err(|e| ParseError::syntax(&e, Some((e.line(), e.column()))))
 }
 
-fn get_file_ext(s: &str) -> Option<&str> {
-    s.rfind('.').map(|pos| &s[pos + 1..])
+// Pelican元数据的值都作为字符串读取，键不区分大小写，不允许重复
+fn parse_pelican_metadata(content: &str) -> Result<AHashIndexMap<String, Value>, ParseError> {
+    let mut frontmatter: AHashIndexMap<String, Value> = AHashIndexMap::default();
+    for entry in metadata_entries(content) {
+        if frontmatter
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(entry.key))
+        {
+            return Err(ParseError::syntax(
+                format!("Duplicate metadata key: {}", entry.key),
+                Some((entry.line, 1)),
+            ));
+        }
+        frontmatter.insert(entry.key.to_owned(), Value::String(entry.value));
+    }
+    Ok(frontmatter)
 }
 
-#[tauri::command]
-fn get_all_files_of_dir(dir: &str) -> Vec<String> {
-    let mut md_files: Vec<String> = Vec::new();
-    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file()
-            && get_file_ext(entry.file_name().to_str().unwrap()) == Some(MD_EXTENSION)
-        {
-            md_files.push(entry.path().to_string_lossy().into_owned());
+// 根据字节偏移计算行列号（从1开始）
+fn line_column_at(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+// 将TOML的值转换为统一使用的YAML值，日期时间转为带标签的值，字符串保持为字符串
+fn toml_to_yaml_value(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Number(i.into()),
+        toml::Value::Float(f) => Value::Number(f.into()),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => DateTimeValue::from_toml(dt).into_value(),
+        toml::Value::Array(arr) => {
+            Value::Sequence(arr.into_iter().map(toml_to_yaml_value).collect())
+        }
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Value::String(key), toml_to_yaml_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+// 只有YAML存在继承的键，其他格式返回空列表
+// TOML有自己的日期时间类型，其他格式中的日期时间字符串会被标记
+fn parse_frontmatter(
+    format: FrontmatterFormat,
+    content: &str,
+) -> Result<(AHashIndexMap<String, Value>, Vec<String>), ParseError> {
+    let parsed = match format {
+        FrontmatterFormat::Yaml => parse_yaml_frontmatter(content),
+        FrontmatterFormat::Toml => parse_toml_frontmatter(content).map(|f| (f, Vec::new())),
+        FrontmatterFormat::Json => parse_json_frontmatter(content).map(|f| (f, Vec::new())),
+        FrontmatterFormat::Pelican => parse_pelican_metadata(content).map(|f| (f, Vec::new())),
+    };
+    // 有起始分隔符时，frontmatter内容从分隔符的下一行开始
+    let lines_before = usize::from(format.delimiter().is_some());
+    let (mut frontmatter, inherited) = parsed.map_err(|e| e.offset_lines(lines_before))?;
+    if format != FrontmatterFormat::Toml {
+        frontmatter.values_mut().for_each(tag_datetimes);
+    }
+    Ok((frontmatter, inherited))
+}
+
+fn read_and_parse_file(file_path: &str) -> ParsedFile {
+    // 先取修改时间再读取内容，读取期间文件被修改时保存会被视为冲突，而不是漏掉修改
+    let read = fs::metadata(file_path)
+        .and_then(|metadata| Ok((metadata.modified().ok(), fs::read(file_path)?)));
+    match read {
+        Ok((modified, bytes)) => ParsedFile {
+            revision: FileRevision::of(&bytes, modified),
+            ..parse_file_bytes(bytes)
+        },
+        Err(e) => ParsedFile {
+            error: Some(e.into()),
+            ..Default::default()
+        },
+    }
+}
+
+// 不包含文件版本，由调用方填写
+fn parse_file_bytes(bytes: Vec<u8>) -> ParsedFile {
+    let document = match Document::parse(bytes) {
+        Ok(document) => document,
+        Err(e) => {
+            return ParsedFile {
+                error: Some(e.into()),
+                ..Default::default()
+            };
+        }
+    };
+
+    let encoding = Some(document.encoding.name().to_owned());
+    let Some(format) = document.format else {
+        // 没有frontmatter
+        return ParsedFile {
+            encoding,
+            ..Default::default()
         };
+    };
+
+    match parse_frontmatter(format, &document.frontmatter) {
+        Ok((frontmatter, inherited)) => ParsedFile {
+            frontmatter,
+            format: Some(format),
+            encoding,
+            inherited,
+            ..Default::default()
+        },
+        Err(e) => ParsedFile {
+            format: Some(format),
+            encoding,
+            error: Some(e),
+            ..Default::default()
+        },
     }
-    md_files.shrink_to_fit();
-    md_files
+}
+
+#[tauri::command]
+fn read_and_parse_yaml_frontmatter(file_path: &str) -> ParsedFile {
+    read_and_parse_file(file_path)
+}
+
+// 传入扩展名列表时只读取匹配的文件，没有变化的文件直接使用解析缓存
+#[tauri::command]
+fn read_and_parse_multiple_frontmatter(
+    app: AppHandle,
+    cache: State<'_, ParseCache>,
+    file_paths: Vec<&str>,
+    extensions: Option<Vec<String>>,
+) -> AHashIndexMap<String, ParsedFile> {
+    cache.load(&app);
+    let parsed = file_paths
+        .par_iter()
+        .filter(|&&file_path| {
+            extensions
+                .as_deref()
+                .is_none_or(|extensions| has_content_extension(Path::new(file_path), extensions))
+        })
+        .map(|&file_path| (file_path.to_owned(), cache.read_and_parse(file_path)))
+        .collect();
+    cache.save();
+    parsed
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -111,16 +294,124 @@ pub fn run() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(stream::StreamRegistry::default())
+        .manage(ParseCache::default())
+        .manage(watch::WatcherState::default())
+        .manage(watch::OwnWrites::default())
+        .manage(journal::JournalLock::default())
         .invoke_handler(tauri::generate_handler![
             read_and_parse_yaml_frontmatter,
             read_and_parse_multiple_frontmatter,
-            get_all_files_of_dir,
+            scan::get_all_files_of_dir,
+            scan::scan_missing_frontmatter,
+            stream::stream_frontmatter,
+            stream::cancel_stream,
+            watch::watch_directory,
+            watch::unwatch_directory,
+            config::load_project_config,
             xlsx::export_frontmatter_to_xlsx,
             xlsx::import_frontmatter_from_xlsx,
             write::write_multiple_frontmatter,
+            journal::list_write_batches,
+            journal::undo_write_batch,
+            journal::redo_write_batch,
+            preview::preview_write_frontmatter,
+            xlsx::preview_import_from_xlsx,
             backup::backup_files_as_tar_zst,
             backup::restore_files_from_tar_zst,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // 文件监视期间更新的解析缓存在退出时写回
+            if let RunEvent::Exit = event {
+                app.state::<ParseCache>().save();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_frontmatter_is_detected() {
+        for original in [
+            "{\n  \"title\": \"a\"\n}\nBody\n",
+            "{ \"title\": \"a\" }\nBody\n",
+        ] {
+            let parsed = parse_file_bytes(original.as_bytes().to_vec());
+            assert_eq!(parsed.format, Some(FrontmatterFormat::Json), "{}", original);
+            assert_eq!(
+                parsed.frontmatter["title"],
+                Value::from("a"),
+                "{}",
+                original
+            );
+            assert!(parsed.error.is_none(), "{}", original);
+        }
+    }
+
+    // 以 { 开头的短代码、Liquid标签或无法解析的JSON都属于正文，文件没有frontmatter
+    #[test]
+    fn braces_that_do_not_start_an_object_are_body() {
+        for original in [
+            "{{< figure src=\"a\" >}}\n\nBody\n",
+            "{% include x.html %}\n\nBody\n",
+            "{\n  \"title\": \n}\nBody\n",
+        ] {
+            let parsed = parse_file_bytes(original.as_bytes().to_vec());
+            assert_eq!(parsed.format, None, "{}", original);
+            assert!(parsed.frontmatter.is_empty(), "{}", original);
+            assert!(parsed.error.is_none(), "{}", original);
+        }
+    }
+
+    #[test]
+    fn toml_frontmatter_is_parsed() {
+        let original = "+++\ntitle = 'a'\ndate = 2023-05-01T12:00:00+08:00\n\n[params]\ndraft = true\n+++\n\nBody\n";
+        let parsed = parse_file_bytes(original.as_bytes().to_vec());
+        assert_eq!(parsed.format, Some(FrontmatterFormat::Toml));
+        assert!(parsed.error.is_none());
+        assert_eq!(parsed.frontmatter["title"], Value::from("a"));
+        assert!(DateTimeValue::from_value(&parsed.frontmatter["date"]).is_some());
+        assert_eq!(parsed.frontmatter["params"]["draft"], Value::Bool(true));
+    }
+
+    // 错误的行号相对于整个文件，包括起始分隔符
+    #[test]
+    fn toml_errors_point_at_the_file_line() {
+        let parsed = parse_file_bytes(b"+++\ntitle = 'a'\ndate = \n+++\n".to_vec());
+        assert_eq!(parsed.format, Some(FrontmatterFormat::Toml));
+        let error = parsed.error.unwrap();
+        assert_eq!((error.kind, error.line), (ParseErrorKind::Syntax, Some(3)));
+    }
+
+    #[test]
+    fn pelican_metadata_is_parsed() {
+        let original = "Title: Hello\nTags: a, b\nSummary: first\n    second\n\nNote: body text\n";
+        let parsed = parse_file_bytes(original.as_bytes().to_vec());
+        assert_eq!(parsed.format, Some(FrontmatterFormat::Pelican));
+        assert!(parsed.error.is_none());
+        assert_eq!(
+            parsed.frontmatter.into_iter().collect::<Vec<_>>(),
+            [
+                ("Title".to_owned(), Value::from("Hello")),
+                ("Tags".to_owned(), Value::from("a, b")),
+                ("Summary".to_owned(), Value::from("first\nsecond")),
+            ]
+        );
+    }
+
+    // 没有 title 的 Key: value 行是普通正文；键不区分大小写，不能重复
+    #[test]
+    fn pelican_metadata_needs_a_title_and_unique_keys() {
+        let parsed = parse_file_bytes(b"Author: me\n\nBody\n".to_vec());
+        assert_eq!(parsed.format, None);
+        assert!(parsed.frontmatter.is_empty());
+
+        let parsed = parse_file_bytes(b"Title: a\ntitle: b\n\nBody\n".to_vec());
+        assert_eq!(parsed.format, Some(FrontmatterFormat::Pelican));
+        assert_eq!(parsed.error.unwrap().line, Some(2));
+    }
 }
@@ -0,0 +1,210 @@
+use crate::{
+    constants::*,
+    revision::FileRevision,
+    write::{
+        ContentGenerator, Prepared, WriteError, WriteErrorKind, WriteOptions, check_extension,
+    },
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
+use serde_yaml_ng::Value;
+use similar::TextDiff;
+use std::{collections::HashMap, io};
+
+use indexmap::IndexMap;
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashMap<K, V> = HashMap<K, V, ahash::RandomState>;
+type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
+
+// 写入后文件的变化；added 表示原文件没有frontmatter，将插入新的frontmatter
+// skipped 与写入时一样，表示按设计不写入的文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewStatus {
+    Added,
+    Changed,
+    Unchanged,
+    Skipped,
+    Conflict,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilePreview {
+    status: PreviewStatus,
+    // 原内容与新内容的统一diff，编码变化时开头另有一行 "encoding: 原编码 → 新编码"；没有变化或无法写入时为空
+    diff: String,
+    // 跳过或无法写入的原因
+    error_kind: Option<WriteErrorKind>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PreviewSummary {
+    added: usize,
+    changed: usize,
+    unchanged: usize,
+    skipped: usize,
+    conflict: usize,
+    failed: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PreviewReport {
+    files: AHashIndexMap<String, FilePreview>,
+    summary: PreviewSummary,
+}
+
+fn preview_file(
+    file_path: &str,
+    data: &AHashIndexMap<String, Value>,
+    extensions: Option<&[String]>,
+    options: &WriteOptions,
+    expected: Option<&FileRevision>,
+) -> FilePreview {
+    diff_file(file_path, data, extensions, options, expected).unwrap_or_else(|e| FilePreview {
+        status: if e.kind.is_skip() {
+            PreviewStatus::Skipped
+        } else {
+            PreviewStatus::Failed
+        },
+        diff: String::new(),
+        error_kind: Some(e.kind),
+        error: Some(e.message),
+    })
+}
+
+// 与写入时的判断和生成器相同，新内容只计算哈希，不保存在内存中
+fn diff_file(
+    file_path: &str,
+    data: &AHashIndexMap<String, Value>,
+    extensions: Option<&[String]>,
+    options: &WriteOptions,
+    expected: Option<&FileRevision>,
+) -> Result<FilePreview, WriteError> {
+    let preview = |status| FilePreview {
+        status,
+        diff: String::new(),
+        error_kind: None,
+        error: None,
+    };
+    check_extension(file_path, extensions)?;
+    let mut generator = match ContentGenerator::open(file_path, data, options, expected)? {
+        Prepared::Ready(generator) => generator,
+        Prepared::Conflict(_) => return Ok(preview(PreviewStatus::Conflict)),
+    };
+    let sha256 = match generator.write_to(&mut io::sink(), &mut io::sink())? {
+        Prepared::Ready(sha256) => sha256,
+        Prepared::Conflict(_) => return Ok(preview(PreviewStatus::Conflict)),
+    };
+    if generator.revision.sha256.as_ref() == Some(&sha256) {
+        return Ok(preview(PreviewStatus::Unchanged));
+    }
+
+    // 正文的文本不会变化，只比较开头的部分；按各自的编码解码后比较，文本相同时只有编码变化
+    // 编码变化时在diff开头单独写一行，只改变编码的文件也能看出改了什么
+    let (original, content) = generator.leading_text(PREVIEW_CONTEXT_LINES)?;
+    let mut diff = String::new();
+    let original_encoding = generator.document.encoding;
+    if original_encoding != generator.encoding {
+        diff = format!(
+            "encoding: {} → {}\n",
+            original_encoding.name(),
+            generator.encoding.name()
+        );
+    }
+    diff += &TextDiff::from_lines(&original, &content)
+        .unified_diff()
+        .header(file_path, file_path)
+        .to_string();
+    Ok(FilePreview {
+        status: if generator.document.format.is_some() {
+            PreviewStatus::Changed
+        } else {
+            PreviewStatus::Added
+        },
+        diff,
+        error_kind: None,
+        error: None,
+    })
+}
+
+// 与 write_multiple_frontmatter 的参数和判断相同，但不写入任何文件
+pub fn preview_write(
+    file_data: &AHashHashMap<String, AHashIndexMap<String, Value>>,
+    extensions: Option<&[String]>,
+    options: &WriteOptions,
+    revisions: &AHashHashMap<String, FileRevision>,
+) -> PreviewReport {
+    let previews: Vec<(String, FilePreview)> = file_data
+        .par_iter()
+        .map(|(file_path, frontmatter)| {
+            let expected = revisions.get(file_path);
+            let preview = preview_file(file_path, frontmatter, extensions, options, expected);
+            (file_path.clone(), preview)
+        })
+        .collect();
+
+    let mut report = PreviewReport::default();
+    for (file_path, preview) in previews {
+        let count = match preview.status {
+            PreviewStatus::Added => &mut report.summary.added,
+            PreviewStatus::Changed => &mut report.summary.changed,
+            PreviewStatus::Unchanged => &mut report.summary.unchanged,
+            PreviewStatus::Skipped => &mut report.summary.skipped,
+            PreviewStatus::Conflict => &mut report.summary.conflict,
+            PreviewStatus::Failed => &mut report.summary.failed,
+        };
+        *count += 1;
+        report.files.insert(file_path, preview);
+    }
+    report.files.sort_unstable_keys();
+    report
+}
+
+// 预览 write_multiple_frontmatter 将要做出的修改
+#[tauri::command]
+pub fn preview_write_frontmatter(
+    file_data: AHashHashMap<String, AHashIndexMap<String, Value>>,
+    extensions: Option<Vec<String>>,
+    options: Option<WriteOptions>,
+    revisions: Option<AHashHashMap<String, FileRevision>>,
+) -> PreviewReport {
+    preview_write(
+        &file_data,
+        extensions.as_deref(),
+        &options.unwrap_or_default(),
+        &revisions.unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::encode;
+    use encoding_rs::GBK;
+    use std::fs;
+
+    #[test]
+    fn encoding_only_changes_are_shown_in_the_diff() {
+        let original = "---\ntitle: 标题\n---\n\n这是一篇使用GBK编码保存的中文文章，正文足够长以便检测编码。\n";
+        let path = std::env::temp_dir().join("cyrene-test-preview-encoding.md");
+        fs::write(&path, encode(original, GBK).unwrap()).unwrap();
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("标题"))]);
+
+        let file_path = path.to_str().unwrap();
+        let options = WriteOptions {
+            convert_to_utf8: true,
+            ..Default::default()
+        };
+        let preview = preview_file(file_path, &data, None, &options, None);
+        assert_eq!(preview.status, PreviewStatus::Changed);
+        assert_eq!(preview.diff, "encoding: GBK → UTF-8\n");
+
+        let preview = preview_file(file_path, &data, None, &WriteOptions::default(), None);
+        assert_eq!(preview.status, PreviewStatus::Unchanged);
+        assert_eq!(preview.diff, "");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 文件的版本：内容的SHA-256和修改时间（自UNIX纪元起的毫秒数）
+// 读取时返回给前端，保存时传回，用于检查文件是否已被其他程序修改
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileRevision {
+    pub sha256: Option<String>,
+    pub mtime: Option<u64>,
+}
+
+impl FileRevision {
+    pub fn of(bytes: &[u8], modified: Option<SystemTime>) -> Self {
+        Self::new(format!("{:x}", Sha256::digest(bytes)), modified)
+    }
+
+    // 内容的哈希已经在读写时按块计算好
+    pub fn new(sha256: String, modified: Option<SystemTime>) -> Self {
+        Self {
+            sha256: Some(sha256),
+            mtime: modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .and_then(|duration| u64::try_from(duration.as_millis()).ok()),
+        }
+    }
+
+    // 提供了哈希时按内容比较，文件只是被touch过不算修改；只提供修改时间时按修改时间比较
+    pub fn matches(&self, current: &Self) -> bool {
+        match (&self.sha256, self.mtime) {
+            (Some(sha256), _) => current.sha256.as_ref() == Some(sha256),
+            (None, Some(mtime)) => current.mtime == Some(mtime),
+            (None, None) => true,
+        }
+    }
+}
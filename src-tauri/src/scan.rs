@@ -0,0 +1,416 @@
+use crate::{
+    config::{ProjectConfig, has_content_extension},
+    constants::*,
+    document::Document,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::{
+    WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashMap<K, V> = HashMap<K, V, ahash::RandomState>;
+
+// 扫描选项，未指定的字段使用项目配置中的值
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    extensions: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    include_hidden: Option<bool>,
+    respect_ignore_files: Option<bool>,
+}
+
+impl ScanOptions {
+    fn resolve(self, config: ProjectConfig) -> ProjectConfig {
+        ProjectConfig {
+            extensions: self.extensions.unwrap_or(config.extensions),
+            include: self.include.unwrap_or(config.include),
+            exclude: self.exclude.unwrap_or(config.exclude),
+            include_hidden: self.include_hidden.unwrap_or(config.include_hidden),
+            respect_ignore_files: self
+                .respect_ignore_files
+                .unwrap_or(config.respect_ignore_files),
+            ..config
+        }
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("{}{}", ERROR_INVALID_GLOB, e))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("{}{}", ERROR_INVALID_GLOB, e))
+}
+
+// 相对于根目录、统一使用 / 分隔的路径，供 glob 匹配
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+// 与扫描目录时相同的忽略规则：全局忽略文件、.git/info/exclude，以及工作区和上级目录中的 .gitignore、.ignore
+// 每个目录的规则在第一次用到时读取，忽略文件变化后需要清空重新读取
+struct IgnoreRules {
+    global: Gitignore,
+    dirs: Mutex<AHashHashMap<PathBuf, Arc<Vec<Gitignore>>>>,
+}
+
+impl IgnoreRules {
+    fn new() -> Self {
+        Self {
+            global: Gitignore::global().0,
+            dirs: Mutex::default(),
+        }
+    }
+
+    // 目录中的忽略规则，按优先级从高到低排列；读取失败的文件忽略
+    fn rules_in(&self, dir: &Path) -> Arc<Vec<Gitignore>> {
+        let mut dirs = self.dirs.lock().unwrap_or_else(PoisonError::into_inner);
+        dirs.entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let files = IGNORE_FILES
+                    .iter()
+                    .map(|name| dir.join(name))
+                    .chain(std::iter::once(dir.join(GIT_EXCLUDE_FILE)));
+                Arc::new(
+                    files
+                        .filter(|path| path.is_file())
+                        .filter_map(|path| {
+                            let mut builder = GitignoreBuilder::new(dir);
+                            builder.add(path);
+                            builder.build().ok()
+                        })
+                        .collect(),
+                )
+            })
+            .clone()
+    }
+
+    // 与遍历目录时一样，文件和其中任一级目录被忽略时文件都被忽略
+    fn is_ignored(&self, root: &Path, relative: &Path) -> bool {
+        let mut path = root.to_path_buf();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            let parent = path.clone();
+            path.push(component);
+            if self.matched(&parent, &path, components.peek().is_some()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // dir 及其上级目录中的规则都适用，越深的目录优先
+    fn matched(&self, dir: &Path, path: &Path, is_dir: bool) -> bool {
+        for ancestor in dir.ancestors() {
+            for rules in self.rules_in(ancestor).iter() {
+                let matched = rules.matched(path, is_dir);
+                if !matched.is_none() {
+                    return matched.is_ignore();
+                }
+            }
+        }
+        self.global.matched(path, is_dir).is_ignore()
+    }
+
+    fn clear(&self) {
+        self.dirs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+// 按扫描选项判断单个文件是否属于工作区，用于处理文件监视的事件
+pub struct FileFilter {
+    root: PathBuf,
+    extensions: Vec<String>,
+    include: GlobSet,
+    exclude: GlobSet,
+    include_hidden: bool,
+    // 不遵循忽略文件时为 None
+    ignore: Option<IgnoreRules>,
+}
+
+impl FileFilter {
+    pub fn new(dir: &str, options: Option<ScanOptions>) -> Result<Self, String> {
+        let options = options
+            .unwrap_or_default()
+            .resolve(ProjectConfig::load(Path::new(dir))?);
+        Ok(Self {
+            root: PathBuf::from(dir),
+            include: build_glob_set(&options.include)?,
+            exclude: build_glob_set(&options.exclude)?,
+            extensions: options.extensions,
+            include_hidden: options.include_hidden,
+            ignore: options.respect_ignore_files.then(IgnoreRules::new),
+        })
+    }
+
+    // relative 为相对于根目录的路径，其中任一级目录被排除、隐藏或忽略时文件也被排除
+    pub fn matches(&self, relative: &Path) -> bool {
+        if !has_content_extension(relative, &self.extensions) {
+            return false;
+        }
+
+        let mut current = PathBuf::new();
+        for component in relative.components() {
+            current.push(component);
+            let hidden = component.as_os_str().to_string_lossy().starts_with('.');
+            if (hidden && !self.include_hidden)
+                || self
+                    .exclude
+                    .is_match(relative_path(Path::new(""), &current))
+            {
+                return false;
+            }
+        }
+
+        if self
+            .ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.is_ignored(&self.root, relative))
+        {
+            return false;
+        }
+
+        self.include.is_empty()
+            || self
+                .include
+                .is_match(relative_path(Path::new(""), relative))
+    }
+
+    // 忽略文件被修改后调用，之后按新的规则判断
+    pub fn reload_ignore_rules(&self) {
+        if let Some(ignore) = &self.ignore {
+            ignore.clear();
+        }
+    }
+}
+
+// 是否为会影响扫描结果的忽略文件
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| IGNORE_FILES.iter().any(|file| name == *file))
+        || path.ends_with(GIT_EXCLUDE_FILE)
+}
+
+// 遍历目录，每找到一个内容文件就交给 on_file 处理，on_file 返回错误时停止遍历
+// 传入取消标记时，标记被设置后停止遍历
+pub fn walk_files(
+    dir: &str,
+    options: Option<ScanOptions>,
+    cancelled: Option<&AtomicBool>,
+    mut on_file: impl FnMut(String) -> Result<(), String>,
+) -> Result<(), String> {
+    let root = PathBuf::from(dir);
+    let options = options
+        .unwrap_or_default()
+        .resolve(ProjectConfig::load(&root)?);
+
+    let include = build_glob_set(&options.include)?;
+    let exclude = build_glob_set(&options.exclude)?;
+
+    let mut builder = WalkBuilder::new(&root);
+    builder
+        .standard_filters(options.respect_ignore_files)
+        .hidden(!options.include_hidden)
+        .require_git(false);
+
+    // 被排除的目录直接跳过，不再进入
+    let filter_root = root.clone();
+    let filter_exclude = exclude.clone();
+    builder.filter_entry(move |entry| {
+        entry.depth() == 0 || !filter_exclude.is_match(relative_path(&filter_root, entry.path()))
+    });
+
+    for entry in builder.build().filter_map(|e| e.ok()) {
+        if cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Relaxed)) {
+            break;
+        }
+        if !entry.file_type().is_some_and(|t| t.is_file())
+            || !has_content_extension(entry.path(), &options.extensions)
+        {
+            continue;
+        }
+
+        if !include.is_empty() && !include.is_match(relative_path(&root, entry.path())) {
+            continue;
+        }
+
+        on_file(entry.path().to_string_lossy().into_owned())?;
+    }
+    Ok(())
+}
+
+pub fn collect_files(dir: &str, options: Option<ScanOptions>) -> Result<Vec<String>, String> {
+    let mut md_files: Vec<String> = Vec::new();
+    walk_files(dir, options, None, |file_path| {
+        md_files.push(file_path);
+        Ok(())
+    })?;
+    md_files.shrink_to_fit();
+    Ok(md_files)
+}
+
+#[tauri::command]
+pub fn get_all_files_of_dir(
+    dir: &str,
+    options: Option<ScanOptions>,
+) -> Result<Vec<String>, String> {
+    collect_files(dir, options)
+}
+
+// 扫描报告：列出没有frontmatter的文件，便于批量导入纯Markdown文件前检查
+#[derive(Debug, Serialize)]
+pub struct FrontmatterReport {
+    total_files: usize,
+    missing_frontmatter: Vec<String>,
+}
+
+// 无法读取的文件不计入缺少frontmatter的列表
+#[tauri::command]
+pub fn scan_missing_frontmatter(
+    dir: &str,
+    options: Option<ScanOptions>,
+) -> Result<FrontmatterReport, String> {
+    let files = collect_files(dir, options)?;
+    let missing_frontmatter = files
+        .par_iter()
+        .filter(|file_path| {
+            Document::read(file_path).is_ok_and(|document| document.format.is_none())
+        })
+        .cloned()
+        .collect();
+
+    Ok(FrontmatterReport {
+        total_files: files.len(),
+        missing_frontmatter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const FILES: &[&str] = &[
+        "a.md",
+        "x.draft.md",
+        "keep.draft.md",
+        "notes.txt",
+        "build/c.md",
+        "posts/b.draft.md",
+        "posts/d.md",
+        ".hidden/e.md",
+        "archive/f.md",
+    ];
+
+    // .gitignore 忽略草稿和 build 目录，.ignore 和下级目录的 .gitignore 再分别放回一个草稿
+    fn site(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cyrene-test-scan-{}", name));
+        let _ = fs::remove_dir_all(&root);
+        for file in FILES {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "---\ntitle: a\n---\n").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "*.draft.md\nbuild/\n").unwrap();
+        fs::write(root.join(".ignore"), "!keep.draft.md\n").unwrap();
+        fs::write(root.join("posts/.gitignore"), "!b.draft.md\n").unwrap();
+        root
+    }
+
+    // 遍历目录和监视时的逐个判断结果必须一致
+    fn scanned(root: &Path, options: impl Fn() -> ScanOptions) -> Vec<String> {
+        let dir = root.to_str().unwrap();
+        let mut files: Vec<String> = collect_files(dir, Some(options()))
+            .unwrap()
+            .iter()
+            .map(|file| relative_path(root, Path::new(file)))
+            .collect();
+        files.sort_unstable();
+
+        let filter = FileFilter::new(dir, Some(options())).unwrap();
+        let mut matched: Vec<String> = FILES
+            .iter()
+            .filter(|file| filter.matches(Path::new(file)))
+            .map(|file| file.to_string())
+            .collect();
+        matched.sort_unstable();
+        assert_eq!(files, matched);
+        files
+    }
+
+    #[test]
+    fn ignore_files_follow_their_precedence() {
+        let root = site("ignore");
+        let options = || ScanOptions {
+            exclude: Some(vec!["archive".to_owned()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            scanned(&root, options),
+            ["a.md", "keep.draft.md", "posts/b.draft.md", "posts/d.md"]
+        );
+
+        let options = || ScanOptions {
+            respect_ignore_files: Some(false),
+            include_hidden: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            scanned(&root, options),
+            [
+                ".hidden/e.md",
+                "a.md",
+                "archive/f.md",
+                "build/c.md",
+                "keep.draft.md",
+                "posts/b.draft.md",
+                "posts/d.md",
+                "x.draft.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn include_and_exclude_globs_are_applied() {
+        let root = site("globs");
+        let options = || ScanOptions {
+            include: Some(vec!["posts/**".to_owned(), "archive/**".to_owned()]),
+            exclude: Some(vec!["**/b.*".to_owned()]),
+            ..Default::default()
+        };
+        assert_eq!(scanned(&root, options), ["archive/f.md", "posts/d.md"]);
+
+        let options = || ScanOptions {
+            extensions: Some(vec!["md".to_owned(), "txt".to_owned()]),
+            exclude: Some(vec!["posts".to_owned(), "archive".to_owned()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            scanned(&root, options),
+            ["a.md", "keep.draft.md", "notes.txt"]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    ParsedFile,
+    cache::ParseCache,
+    constants::*,
+    scan::{ScanOptions, walk_files},
+};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+use tauri::{AppHandle, Manager, State, ipc::Channel};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use indexmap::IndexMap;
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashMap<K, V> = HashMap<K, V, ahash::RandomState>;
+type AHashHashSet<T> = HashSet<T, ahash::RandomState>;
+type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
+
+// 通过通道发送给前端的扫描进度
+// 遍历目录的同时解析，文件总数在遍历完成后才确定，因此只有 Finished 带有总数
+#[derive(Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum StreamEvent {
+    Started,
+    // 一批文件的解析结果，done 为目前已解析的文件数
+    Chunk {
+        files: AHashIndexMap<String, ParsedFile>,
+        done: usize,
+    },
+    Finished {
+        done: usize,
+        total: usize,
+        cancelled: bool,
+    },
+    Failed {
+        message: String,
+    },
+}
+
+// 正在进行的扫描及其取消标记
+#[derive(Default)]
+pub struct StreamRegistry {
+    next_id: AtomicU64,
+    running: Mutex<AHashHashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl StreamRegistry {
+    fn register(&self) -> (u64, Arc<AtomicBool>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, cancelled.clone());
+        (id, cancelled)
+    }
+
+    fn remove(&self, id: u64) {
+        self.running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&id);
+    }
+
+    fn cancel(&self, id: u64) -> bool {
+        match self
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&id)
+        {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn parse_chunk(files: &[String], cache: &ParseCache) -> AHashIndexMap<String, ParsedFile> {
+    files
+        .par_iter()
+        .map(|file_path| (file_path.clone(), cache.read_and_parse(file_path)))
+        .collect()
+}
+
+// 遍历目录的同时分批解析，每找到一批文件就发送，不等待遍历完成
+fn run_stream(
+    dir: &str,
+    options: Option<ScanOptions>,
+    cancelled: &AtomicBool,
+    cache: &ParseCache,
+    on_event: &Channel<StreamEvent>,
+) -> Result<(), String> {
+    let send = |event| on_event.send(event).map_err(|e| e.to_string());
+    send(StreamEvent::Started)?;
+
+    let mut done = 0;
+    let mut pending = Vec::with_capacity(STREAM_CHUNK_SIZE);
+    let mut seen = AHashHashSet::default();
+    walk_files(dir, options, Some(cancelled), |file_path| {
+        seen.insert(file_path.clone());
+        pending.push(file_path);
+        if pending.len() < STREAM_CHUNK_SIZE {
+            return Ok(());
+        }
+        done += pending.len();
+        let files = parse_chunk(&pending, cache);
+        pending.clear();
+        send(StreamEvent::Chunk { files, done })
+    })?;
+
+    let total = done + pending.len();
+    if !pending.is_empty() && !cancelled.load(Ordering::Relaxed) {
+        done = total;
+        send(StreamEvent::Chunk {
+            files: parse_chunk(&pending, cache),
+            done,
+        })?;
+    }
+
+    // 完整扫描后才能确定哪些文件已经不在目录中
+    let cancelled = cancelled.load(Ordering::Relaxed);
+    if !cancelled {
+        cache.forget_unseen(Path::new(dir), &seen);
+    }
+    send(StreamEvent::Finished {
+        done,
+        total,
+        cancelled,
+    })
+}
+
+// 在后台线程中扫描目录并分批解析frontmatter，立即返回扫描id，结果通过通道分批发送
+#[tauri::command]
+pub fn stream_frontmatter(
+    app: AppHandle,
+    dir: String,
+    options: Option<ScanOptions>,
+    on_event: Channel<StreamEvent>,
+) -> u64 {
+    let (id, cancelled) = app.state::<StreamRegistry>().register();
+
+    std::thread::spawn(move || {
+        let cache = app.state::<ParseCache>();
+        cache.load(&app);
+        if let Err(message) = run_stream(&dir, options, &cancelled, &cache, &on_event) {
+            eprintln!("Frontmatter stream failed: {}", message);
+            let _ = on_event.send(StreamEvent::Failed { message });
+        }
+        cache.save();
+        app.state::<StreamRegistry>().remove(id);
+    });
+
+    id
+}
+
+// 返回是否找到了正在进行的扫描
+#[tauri::command]
+pub fn cancel_stream(stream_id: u64, registry: State<'_, StreamRegistry>) -> bool {
+    registry.cancel(stream_id)
+}
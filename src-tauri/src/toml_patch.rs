@@ -0,0 +1,194 @@
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+// 将新值转换为行内的TOML值，写在原来的位置
+fn inline_value(value: &toml::Value) -> Option<toml_edit::Value> {
+    value.to_string().parse().ok()
+}
+
+// 新增的键：表写为 [key] 表，其他的值写在行内
+fn new_item(value: &toml::Value) -> Option<Item> {
+    match value {
+        toml::Value::Table(new) => {
+            let mut table = Table::new();
+            patch_table(&mut table, &toml::Table::new(), new)?;
+            Some(Item::Table(table))
+        }
+        _ => inline_value(value).map(Item::Value),
+    }
+}
+
+// 数组中的元素都是表时，按 [[key]] 表数组逐个修改
+fn patch_array_of_tables(
+    array: &mut ArrayOfTables,
+    old: Option<&toml::Value>,
+    new: &[toml::Value],
+) -> Option<()> {
+    let empty = toml::Table::new();
+    let old = old.and_then(toml::Value::as_array);
+    while array.len() > new.len() {
+        array.remove(array.len() - 1);
+    }
+    for (index, value) in new.iter().enumerate() {
+        let new = value.as_table()?;
+        let old = old
+            .and_then(|old| old.get(index))
+            .and_then(toml::Value::as_table)
+            .unwrap_or(&empty);
+        match array.get_mut(index) {
+            Some(table) => patch_table(table, old, new)?,
+            None => {
+                let mut table = Table::new();
+                patch_table(&mut table, &empty, new)?;
+                array.push(table);
+            }
+        }
+    }
+    Some(())
+}
+
+// 值未变化的键保持原样，修改的键只替换它的值并保留注释，删除的键连同其上方的注释一起移除，新增的键追加在表的末尾
+fn patch_table(table: &mut Table, old: &toml::Table, new: &toml::Table) -> Option<()> {
+    table.retain(|key, _| new.contains_key(key));
+    for (key, value) in new {
+        let old_value = old.get(key);
+        if old_value == Some(value) && table.contains_key(key) {
+            continue;
+        }
+
+        match (table.get_mut(key), value) {
+            (Some(Item::Table(table)), toml::Value::Table(new)) => {
+                let empty = toml::Table::new();
+                let old = old_value.and_then(toml::Value::as_table).unwrap_or(&empty);
+                patch_table(table, old, new)?;
+            }
+            (Some(Item::ArrayOfTables(array)), toml::Value::Array(new)) => {
+                patch_array_of_tables(array, old_value, new)?;
+            }
+            // 行内的值替换后沿用原来的前后空白和行尾注释
+            (Some(Item::Value(old)), _) => {
+                let mut new = inline_value(value)?;
+                *new.decor_mut() = old.decor().clone();
+                *old = new;
+            }
+            (Some(item), _) => *item = Item::Value(inline_value(value)?),
+            (None, _) => {
+                table.insert(key, new_item(value)?);
+            }
+        }
+    }
+    Some(())
+}
+
+// 按 data 的顺序排列顶层的键，[key] 表之间交换原有的位置
+fn reorder_table(table: &mut Table, data: &toml::Table) {
+    let index = |key: &str| data.keys().position(|k| k == key).unwrap_or(usize::MAX);
+    table.sort_values_by(|a, _, b, _| index(a).cmp(&index(b)));
+
+    let is_section = |item: &Item| {
+        item.as_table()
+            .is_some_and(|table| !table.is_dotted() && table.position().is_some())
+    };
+    let mut positions: Vec<isize> = table
+        .iter()
+        .filter(|(_, item)| is_section(item))
+        .filter_map(|(_, item)| item.as_table()?.position())
+        .collect();
+    positions.sort_unstable();
+    let mut positions = positions.into_iter();
+    for (_, item) in table.iter_mut() {
+        if is_section(item)
+            && let Some(table) = item.as_table_mut()
+        {
+            table.set_position(positions.next());
+        }
+    }
+}
+
+// 在原TOML文本上应用修改，保留注释、引号风格和键的顺序；
+// reorder 时顶层的键按 data 的顺序重新排列。无法保留格式时返回 None
+pub fn patch_toml_frontmatter(original: &str, data: &toml::Table, reorder: bool) -> Option<String> {
+    let old = toml::from_str::<toml::Table>(original).ok()?;
+    let mut document = original.parse::<DocumentMut>().ok()?;
+    patch_table(document.as_table_mut(), &old, data)?;
+    if reorder {
+        reorder_table(document.as_table_mut(), data);
+    }
+
+    // 修改后必须能读回要写入的数据
+    let patched = document.to_string();
+    (toml::from_str::<toml::Table>(&patched).ok()? == *data).then_some(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "\
+# 文章信息
+title = 'A \"quoted\" title'   # 单引号
+date = 2023-05-01T12:00:00+08:00
+tags = [ 'a', 'b' ]
+
+[params]
+# 作者
+author = \"me\"
+draft = false
+
+[[resources]]
+src = 'a.png'
+";
+
+    fn data_of(content: &str) -> toml::Table {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn unchanged_toml_is_kept_byte_for_byte() {
+        let data = data_of(ORIGINAL);
+        for reorder in [false, true] {
+            assert_eq!(
+                patch_toml_frontmatter(ORIGINAL, &data, reorder).as_deref(),
+                Some(ORIGINAL)
+            );
+        }
+    }
+
+    #[test]
+    fn only_edited_keys_are_rewritten() {
+        let mut data = data_of(ORIGINAL);
+        data.insert("title".to_owned(), toml::Value::from("New"));
+        data["params"]
+            .as_table_mut()
+            .unwrap()
+            .insert("draft".to_owned(), toml::Value::from(true));
+        data["resources"].as_array_mut().unwrap()[0]
+            .as_table_mut()
+            .unwrap()
+            .insert("title".to_owned(), toml::Value::from("A"));
+        data.remove("tags");
+        data.insert("slug".to_owned(), toml::Value::from("new-slug"));
+
+        assert_eq!(
+            patch_toml_frontmatter(ORIGINAL, &data, false).unwrap(),
+            ORIGINAL
+                .replace("'A \"quoted\" title'", "\"New\"")
+                .replace("draft = false", "draft = true")
+                .replace("tags = [ 'a', 'b' ]\n", "slug = \"new-slug\"\n")
+                .replace("src = 'a.png'\n", "src = 'a.png'\ntitle = \"A\"\n")
+        );
+    }
+
+    #[test]
+    fn keys_follow_the_data_order_when_reordering() {
+        let original = "title = 'a'\ndate = 2023-05-01\n\n[params]\nx = 1\n\n[extra]\ny = 2\n";
+        let old = data_of(original);
+        let mut data = toml::Table::new();
+        for key in ["extra", "date", "params", "title"] {
+            data.insert(key.to_owned(), old[key].clone());
+        }
+        assert_eq!(
+            patch_toml_frontmatter(original, &data, true).unwrap(),
+            "date = 2023-05-01\ntitle = 'a'\n\n[extra]\ny = 2\n\n[params]\nx = 1\n"
+        );
+    }
+}
@@ -0,0 +1,445 @@
+use crate::{
+    ParsedFile,
+    cache::ParseCache,
+    constants::*,
+    scan::{FileFilter, ScanOptions, is_ignore_file},
+};
+use ignore::WalkBuilder;
+use notify_debouncer_full::{
+    DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache, new_debouncer,
+    notify::{
+        EventKind, RecommendedWatcher, RecursiveMode,
+        event::{ModifyKind, RenameMode},
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashMap<K, V> = HashMap<K, V, ahash::RandomState>;
+
+// 推送给前端的外部文件变化，路径均为工作区中的绝对路径
+#[derive(Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileChange {
+    Created {
+        path: String,
+        file: ParsedFile,
+    },
+    Modified {
+        path: String,
+        file: ParsedFile,
+    },
+    Deleted {
+        path: String,
+    },
+    Renamed {
+        from: String,
+        to: String,
+        file: ParsedFile,
+    },
+}
+
+// 当前打开目录的监视器，被替换或移除时旧的监视器随之停止
+#[derive(Default)]
+pub struct WatcherState {
+    debouncer: Mutex<Option<Debouncer<RecommendedWatcher, RecommendedCache>>>,
+}
+
+// 本应用最近写入的文件内容的SHA-256，监视到的变化与之相同时不推送，避免重新加载自己保存的文件
+#[derive(Default)]
+pub struct OwnWrites {
+    written: Mutex<AHashHashMap<PathBuf, String>>,
+}
+
+impl OwnWrites {
+    // 在替换文件之前调用，监视器可能在写入完成后立即收到事件
+    pub fn expect(&self, file_path: &str, sha256: &str) {
+        self.written
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(PathBuf::from(file_path), sha256.to_owned());
+    }
+
+    // 内容不同时说明文件之后又被其他程序修改过，不再忽略
+    fn is_own(&self, path: &Path, sha256: Option<&str>) -> bool {
+        let mut written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+        match written.get(path) {
+            Some(own) if Some(own.as_str()) == sha256 => true,
+            Some(_) => {
+                written.remove(path);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+struct Workspace {
+    root: PathBuf,
+    // 部分平台（如macOS）上事件中的路径是解析过符号链接的真实路径
+    canonical_root: Option<PathBuf>,
+    filter: FileFilter,
+}
+
+impl Workspace {
+    // 将事件中的路径换算为工作区中的路径，不在工作区目录中时返回 None
+    fn locate(&self, path: &Path) -> Option<PathBuf> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .ok()
+            .or_else(|| path.strip_prefix(self.canonical_root.as_ref()?).ok())?;
+        Some(self.root.join(relative))
+    }
+
+    // 不属于工作区的文件返回 None
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let path = self.locate(path)?;
+        let matches = path
+            .strip_prefix(&self.root)
+            .is_ok_and(|relative| self.filter.matches(relative));
+        matches.then_some(path)
+    }
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+// 目录中的所有文件，是否属于工作区由调用方判断
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    WalkBuilder::new(dir)
+        .standard_filters(false)
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+// 将一批事件转换为推送给前端的变化，一个目录的事件可能对应其中多个文件的变化
+struct ChangeCollector<'a> {
+    workspace: &'a Workspace,
+    cache: &'a ParseCache,
+    own_writes: &'a OwnWrites,
+    changes: Vec<FileChange>,
+}
+
+impl ChangeCollector<'_> {
+    fn parse(&self, path: &Path) -> ParsedFile {
+        self.cache.read_and_parse(&path_string(path))
+    }
+
+    // path 为工作区中的路径；内容与本应用最近写入的相同时不推送
+    fn upsert(&mut self, path: PathBuf, created: bool) {
+        let file = self.parse(&path);
+        if self
+            .own_writes
+            .is_own(&path, file.revision.sha256.as_deref())
+        {
+            return;
+        }
+        let path = path_string(&path);
+        self.changes.push(if created {
+            FileChange::Created { path, file }
+        } else {
+            FileChange::Modified { path, file }
+        });
+    }
+
+    // 文件或目录已不存在，目录中的文件无法再列出，按缓存中记录的文件推送删除
+    fn delete(&mut self, path: &Path) {
+        let Some(path) = self.workspace.locate(path) else {
+            return;
+        };
+        let mut deleted = self.cache.forget_under(&path);
+        let path = path_string(&path);
+        if !deleted.contains(&path) {
+            deleted.push(path);
+        }
+        for path in deleted {
+            if self.workspace.resolve(Path::new(&path)).is_some() {
+                self.changes.push(FileChange::Deleted { path });
+            }
+        }
+    }
+
+    // 从工作区外移入或移出工作区的文件按新建和删除处理
+    fn rename_file(&mut self, from: &Path, to: &Path) {
+        match (self.workspace.resolve(from), self.workspace.resolve(to)) {
+            (Some(from), Some(to)) => self.changes.push(FileChange::Renamed {
+                from: path_string(&from),
+                file: self.parse(&to),
+                to: path_string(&to),
+            }),
+            (Some(from), None) => self.changes.push(FileChange::Deleted {
+                path: path_string(&from),
+            }),
+            (None, Some(to)) => self.upsert(to, true),
+            (None, None) => {}
+        }
+    }
+
+    // 目录被重命名时只有目录本身的事件，其中的文件逐个按重命名处理
+    fn rename(&mut self, from: &Path, to: &Path) {
+        if !to.is_dir() {
+            self.rename_file(from, to);
+            return;
+        }
+        for file in files_in(to) {
+            if let Ok(relative) = file.strip_prefix(to) {
+                self.rename_file(&from.join(relative), &file);
+            }
+        }
+        if let Some(from) = self.workspace.locate(from) {
+            self.cache.forget_under(&from);
+        }
+    }
+
+    fn add(&mut self, event: &DebouncedEvent) {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                self.rename(&event.paths[0], &event.paths[1]);
+            }
+            // 只改变权限、访问时间等元数据时文件内容不变
+            EventKind::Modify(ModifyKind::Metadata(_)) => {}
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                let Some(path) = event.paths.first() else {
+                    return;
+                };
+                let created = matches!(event.kind, EventKind::Create(_));
+                // 只有一侧路径的重命名事件无法区分移入和移出，按当前是否存在判断
+                if path.is_dir() {
+                    // 新建或移入的目录中的文件逐个作为新建推送，目录本身被修改时忽略
+                    let moved = matches!(event.kind, EventKind::Modify(ModifyKind::Name(_)));
+                    if created || moved {
+                        for file in files_in(path) {
+                            if let Some(file) = self.workspace.resolve(&file) {
+                                self.upsert(file, true);
+                            }
+                        }
+                    }
+                } else if path.is_file() {
+                    if let Some(path) = self.workspace.resolve(path) {
+                        self.upsert(path, created);
+                    }
+                } else {
+                    self.delete(path);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn handle_events(app: &AppHandle, workspace: &Workspace, result: DebounceEventResult) {
+    let events = match result {
+        Ok(events) => events,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("File watcher error: {}", e);
+            }
+            return;
+        }
+    };
+
+    // 忽略文件变化后按新的规则判断，已推送的文件不会因此被移除
+    if events
+        .iter()
+        .any(|event| event.paths.iter().any(|path| is_ignore_file(path)))
+    {
+        workspace.filter.reload_ignore_rules();
+    }
+
+    let cache = app.state::<ParseCache>();
+    let own_writes = app.state::<OwnWrites>();
+    let mut collector = ChangeCollector {
+        workspace,
+        cache: &cache,
+        own_writes: &own_writes,
+        changes: Vec::new(),
+    };
+    events.iter().for_each(|event| collector.add(event));
+    let changes = collector.changes;
+    // 不在每批事件后都写回磁盘，停止监视和退出应用时会写回剩余的部分
+    cache.save_if_due(Duration::from_secs(CACHE_SAVE_INTERVAL_SECS));
+
+    if !changes.is_empty()
+        && let Err(e) = app.emit(FILE_CHANGE_EVENT, changes)
+    {
+        eprintln!("Failed to emit file changes: {}", e);
+    }
+}
+
+// 监视目录中内容文件的变化，变化的文件重新解析后推送给前端；再次调用时替换之前的监视器
+#[tauri::command]
+pub fn watch_directory(
+    app: AppHandle,
+    dir: String,
+    options: Option<ScanOptions>,
+    state: State<'_, WatcherState>,
+) -> Result<(), String> {
+    let workspace = Workspace {
+        root: PathBuf::from(&dir),
+        canonical_root: fs::canonicalize(&dir).ok(),
+        filter: FileFilter::new(&dir, options)?,
+    };
+    app.state::<ParseCache>().load(&app);
+
+    let handler_app = app.clone();
+    let mut debouncer = new_debouncer(
+        Duration::from_millis(WATCH_DEBOUNCE_MS),
+        None,
+        move |result| handle_events(&handler_app, &workspace, result),
+    )
+    .map_err(|e| format!("{}{}", ERROR_WATCH, e))?;
+    debouncer
+        .watch(&dir, RecursiveMode::Recursive)
+        .map_err(|e| format!("{}{}", ERROR_WATCH, e))?;
+
+    *state
+        .debouncer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(debouncer);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unwatch_directory(app: AppHandle, state: State<'_, WatcherState>) {
+    state
+        .debouncer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+    app.state::<ParseCache>().save();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify_debouncer_full::notify::{
+        Event,
+        event::{CreateKind, DataChange},
+    };
+    use sha2::{Digest, Sha256};
+    use std::time::Instant;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cyrene-test-watch-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("site")).unwrap();
+        dir
+    }
+
+    fn workspace(root: &Path) -> Workspace {
+        let root = path_string(root);
+        Workspace {
+            canonical_root: None,
+            filter: FileFilter::new(&root, None).unwrap(),
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn collect(collector: &mut ChangeCollector, kind: EventKind, paths: &[&Path]) -> Vec<String> {
+        let event = paths.iter().fold(Event::new(kind), |event, path| {
+            event.add_path(path.to_path_buf())
+        });
+        collector.add(&DebouncedEvent::new(event, Instant::now()));
+        std::mem::take(&mut collector.changes)
+            .into_iter()
+            .map(|change| match change {
+                FileChange::Created { path, .. } => format!("created {}", path),
+                FileChange::Modified { path, .. } => format!("modified {}", path),
+                FileChange::Deleted { path } => format!("deleted {}", path),
+                FileChange::Renamed { from, to, .. } => format!("renamed {} -> {}", from, to),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn directory_events_are_reported_per_file() {
+        let dir = test_dir("dir");
+        let root = dir.join("site");
+        let (posts, articles) = (root.join("posts"), root.join("articles"));
+        fs::create_dir(&posts).unwrap();
+        fs::write(posts.join("a.md"), "---\ntitle: a\n---\n").unwrap();
+        fs::write(posts.join("b.txt"), "not content").unwrap();
+
+        let workspace = workspace(&root);
+        let cache = ParseCache::default();
+        let own_writes = OwnWrites::default();
+        let mut collector = ChangeCollector {
+            workspace: &workspace,
+            cache: &cache,
+            own_writes: &own_writes,
+            changes: Vec::new(),
+        };
+        let file = |dir: &Path| path_string(&dir.join("a.md"));
+
+        let kind = EventKind::Create(CreateKind::Folder);
+        assert_eq!(
+            collect(&mut collector, kind, &[&posts]),
+            [format!("created {}", file(&posts))]
+        );
+
+        fs::rename(&posts, &articles).unwrap();
+        let kind = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        assert_eq!(
+            collect(&mut collector, kind, &[&posts, &articles]),
+            [format!("renamed {} -> {}", file(&posts), file(&articles))]
+        );
+
+        // 移出工作区后目录中的文件无法列出，按缓存中的文件推送删除
+        let outside = dir.join("articles");
+        fs::rename(&articles, &outside).unwrap();
+        let kind = EventKind::Modify(ModifyKind::Name(RenameMode::From));
+        assert_eq!(
+            collect(&mut collector, kind, &[&articles]),
+            [format!("deleted {}", file(&articles))]
+        );
+
+        fs::rename(&outside, &articles).unwrap();
+        let kind = EventKind::Modify(ModifyKind::Name(RenameMode::To));
+        assert_eq!(
+            collect(&mut collector, kind, &[&articles]),
+            [format!("created {}", file(&articles))]
+        );
+    }
+
+    #[test]
+    fn own_writes_are_not_reported() {
+        let dir = test_dir("own");
+        let root = dir.join("site");
+        let path = root.join("post.md");
+        let file_path = path_string(&path);
+        let content = "---\ntitle: saved\n---\n";
+        fs::write(&path, content).unwrap();
+
+        let workspace = workspace(&root);
+        let cache = ParseCache::default();
+        let own_writes = OwnWrites::default();
+        own_writes.expect(&file_path, &format!("{:x}", Sha256::digest(content)));
+        let mut collector = ChangeCollector {
+            workspace: &workspace,
+            cache: &cache,
+            own_writes: &own_writes,
+            changes: Vec::new(),
+        };
+
+        let kind = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        assert!(collect(&mut collector, kind, &[&path]).is_empty());
+
+        fs::write(&path, "---\ntitle: edited elsewhere\n---\n").unwrap();
+        assert_eq!(
+            collect(&mut collector, kind, &[&path]),
+            [format!("modified {}", file_path)]
+        );
+    }
+}
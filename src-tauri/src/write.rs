@@ -1,12 +1,34 @@
-use crate::constants::*;
+use crate::{
+    ParsedFile,
+    atomic_write::StagedFile,
+    config::{KeyOrder, has_content_extension},
+    constants::*,
+    datetime::{DateTimeValue, untag_datetimes},
+    document::Document,
+    encoding::detect_encoding_streamed,
+    format::{FrontmatterFormat, is_metadata_key, metadata_entries},
+    journal::{Journal, JournalEntry},
+    parse_frontmatter, read_and_parse_file,
+    revision::FileRevision,
+    toml_patch::patch_toml_frontmatter,
+    watch::OwnWrites,
+    yaml_emit::{YamlStyle, emit_yaml},
+    yaml_patch::{patch_yaml_frontmatter, uses_anchors},
+};
+use encoding_rs::{Encoding, UTF_8};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fs::{File, OpenOptions},
-    io::{BufRead, BufReader, Error, Write},
+    fmt::Display,
+    fs::{self, File},
+    io::{Error, ErrorKind, Write},
+    path::Path,
 };
 
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde_yaml_ng::Value;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
 
 use indexmap::IndexMap;
 
@@ -14,84 +36,1375 @@ use indexmap::IndexMap;
 type AHashHashMap<K, V> = HashMap<K, V, ahash::RandomState>;
 type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
 
-fn write_frontmatter(file_path: &str, frontmatter_content: &str) -> Result<(), Error> {
-    // 读取原文件内容
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-
-    let mut frontmatter_started = false;
-    let mut frontmatter_ended = false;
-    let mut content_after_frontmatter = String::new();
-
-    // 跳过原有的frontmatter，保存后续内容
-    for line_res in reader.lines() {
-        let line = line_res?;
-        if line.starts_with(FRONTMATTER_DELIMITER) {
-            if frontmatter_started && !frontmatter_ended {
-                frontmatter_ended = true;
-                continue;
-            } else if !frontmatter_started {
-                frontmatter_started = true;
-                continue;
+fn serialize_yaml_frontmatter(
+    data: &AHashIndexMap<String, Value>,
+    style: Option<&YamlStyle>,
+) -> Result<String, Error> {
+    // 空的frontmatter只保留分隔符，不写入 {}
+    if data.is_empty() {
+        return Ok(String::new());
+    }
+
+    // 按指定的格式写出，无法保证读回一致时（如带标签的值）使用默认格式
+    if let Some(style) = style {
+        match emit_yaml(data, style) {
+            Some(yaml_string) => return Ok(yaml_string),
+            None => {
+                eprintln!("YAML style cannot represent this frontmatter, using the default style")
             }
         }
+    }
 
-        if frontmatter_ended || !frontmatter_started {
-            content_after_frontmatter.push_str(&line);
-            content_after_frontmatter.push('\n');
+    match serde_yaml_ng::to_string(data) {
+        Ok(yaml_string) => Ok(yaml_string),
+        Err(e) => {
+            eprintln!("Error serializing YAML frontmatter: {}", e);
+            Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Failed to serialize YAML frontmatter",
+            ))
         }
     }
+}
 
-    // 写入新内容
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(file_path)?;
-
-    // 写入frontmatter
-    writeln!(file, "{}", FRONTMATTER_DELIMITER)?;
-    write!(file, "{}", frontmatter_content)?;
-    writeln!(file, "{}", FRONTMATTER_DELIMITER)?;
-
-    // 写入原有内容
-    write!(file, "{}", content_after_frontmatter)?;
+// 优先在原文本上修改，保留注释、引号风格和键的顺序，无法保留时重新生成
+fn serialize_toml_frontmatter(
+    original: &str,
+    data: &AHashIndexMap<String, Value>,
+    reorder: bool,
+) -> Result<String, Error> {
+    let mut table = toml::Table::new();
+    for (key, value) in data {
+        // TOML 没有 null，直接省略该字段
+        if let Some(toml_value) = yaml_to_toml_value(value)? {
+            table.insert(key.clone(), toml_value);
+        }
+    }
 
-    Ok(())
+    if let Some(patched) = patch_toml_frontmatter(original, &table, reorder) {
+        return Ok(patched);
+    }
+    match toml::to_string(&table) {
+        Ok(toml_string) => Ok(toml_string),
+        Err(e) => {
+            eprintln!("Error serializing TOML frontmatter: {}", e);
+            Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Failed to serialize TOML frontmatter",
+            ))
+        }
+    }
 }
 
-fn serialize_yaml_frontmatter(data: &AHashIndexMap<String, Value>) -> Result<String, Error> {
-    match serde_yaml_ng::to_string(data) {
-        Ok(yaml_string) => Ok(yaml_string),
+// 按原对象的缩进输出JSON，保持文件原有风格
+fn serialize_json_frontmatter(
+    data: &AHashIndexMap<String, Value>,
+    indent: &str,
+) -> Result<String, Error> {
+    let mut buffer = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+
+    match data.serialize(&mut serializer) {
+        Ok(()) => {
+            buffer.push(b'\n');
+            String::from_utf8(buffer).map_err(|e| Error::new(std::io::ErrorKind::InvalidData, e))
+        }
         Err(e) => {
-            eprintln!("Error serializing YAML frontmatter: {}", e);
+            eprintln!("Error serializing JSON frontmatter: {}", e);
             Err(Error::new(
                 std::io::ErrorKind::InvalidData,
-                "Failed to serialize YAML frontmatter",
+                "Failed to serialize JSON frontmatter",
             ))
         }
     }
 }
 
-fn write_yaml_frontmatter(
+// 取原JSON对象中第一处缩进作为缩进单位
+fn detect_json_indent(frontmatter: &str) -> &str {
+    frontmatter
+        .lines()
+        .skip(1)
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or(DEFAULT_JSON_INDENT)
+}
+
+// Pelican元数据的值都是字符串，列表以逗号连接
+fn pelican_value_string(value: &Value) -> Result<String, Error> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        Value::Sequence(seq) => Ok(seq
+            .iter()
+            .map(pelican_value_string)
+            .collect::<Result<Vec<_>, _>>()?
+            .join(COMMA_SEPARATOR)),
+        Value::Mapping(_) => Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Pelican metadata cannot contain nested mappings",
+        )),
+        Value::Tagged(tagged) => pelican_value_string(&tagged.value),
+    }
+}
+
+// 多行的值写为缩进的续行，空行会结束元数据，因此被省略
+fn format_pelican_entry(key: &str, value: &str) -> Result<String, Error> {
+    if !is_metadata_key(key) {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid Pelican metadata key: {}", key),
+        ));
+    }
+
+    let mut lines = value.lines().map(str::trim).filter(|line| !line.is_empty());
+    let mut entry = format!("{}:", key);
+    if let Some(first) = lines.next() {
+        entry.push(' ');
+        entry.push_str(first);
+    }
+    entry.push('\n');
+    for line in lines {
+        entry.push_str(PELICAN_CONTINUATION_INDENT);
+        entry.push_str(line);
+        entry.push('\n');
+    }
+    Ok(entry)
+}
+
+// 在原元数据上修改：值未变化的键保持原样，修改的键重写，删除的键移除
+// 保持原顺序时新增的键追加在末尾，reorder 为 true 时按 data 中的顺序输出
+fn serialize_pelican_metadata(
+    original: &str,
+    data: &AHashIndexMap<String, Value>,
+    reorder: bool,
+) -> Result<String, Error> {
+    // 没有 title 的元数据再次读取时无法被识别
+    if !data
+        .keys()
+        .any(|key| key.eq_ignore_ascii_case(PELICAN_TITLE_KEY))
+    {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Pelican metadata must contain a title",
+        ));
+    }
+
+    let entries = metadata_entries(original);
+    let original_entry = |key: &str| entries.iter().find(|entry| entry.key == key);
+    let keys: Vec<&str> = if reorder {
+        data.keys().map(String::as_str).collect()
+    } else {
+        entries
+            .iter()
+            .map(|entry| entry.key)
+            .filter(|&key| data.contains_key(key))
+            .chain(
+                data.keys()
+                    .map(String::as_str)
+                    .filter(|&key| original_entry(key).is_none()),
+            )
+            .collect()
+    };
+
+    let mut res = String::with_capacity(original.len());
+    for key in keys {
+        let value = pelican_value_string(&data[key])?;
+        match original_entry(key) {
+            Some(entry) if entry.value == value => res.push_str(entry.text),
+            _ => res.push_str(&format_pelican_entry(key, &value)?),
+        }
+    }
+    Ok(res)
+}
+
+// 将YAML值转换为TOML值，null 返回 None；只有带标签的日期时间写为TOML日期时间，字符串始终是字符串
+fn yaml_to_toml_value(value: &Value) -> Result<Option<toml::Value>, Error> {
+    if let Some(datetime) = DateTimeValue::from_value(value) {
+        return Ok(Some(toml::Value::Datetime(datetime.to_toml())));
+    }
+    let toml_value = match value {
+        Value::Null => return Ok(None),
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                toml::Value::Integer(i)
+            } else if let Some(f) = n.as_f64() {
+                toml::Value::Float(f)
+            } else {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Number out of TOML range: {}", n),
+                ));
+            }
+        }
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Sequence(seq) => {
+            let mut array = toml::value::Array::with_capacity(seq.len());
+            for item in seq {
+                match yaml_to_toml_value(item)? {
+                    Some(toml_item) => array.push(toml_item),
+                    None => {
+                        return Err(Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "TOML arrays cannot contain null values",
+                        ));
+                    }
+                }
+            }
+            toml::Value::Array(array)
+        }
+        Value::Mapping(mapping) => {
+            let mut table = toml::Table::new();
+            for (key, value) in mapping {
+                let key = match key {
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    Value::Bool(b) => b.to_string(),
+                    _ => {
+                        return Err(Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "TOML table keys must be strings",
+                        ));
+                    }
+                };
+                if let Some(toml_value) = yaml_to_toml_value(value)? {
+                    table.insert(key, toml_value);
+                }
+            }
+            toml::Value::Table(table)
+        }
+        Value::Tagged(tagged) => return yaml_to_toml_value(&tagged.value),
+    };
+
+    Ok(Some(toml_value))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WriteOptions {
+    // 将非UTF-8编码的文件转换为UTF-8，默认保持原编码
+    pub convert_to_utf8: bool,
+    // 在没有frontmatter的文件开头插入新的frontmatter，默认跳过这些文件
+    pub insert_missing: bool,
+    // 插入的frontmatter使用的格式
+    pub insert_format: FrontmatterFormat,
+    // 将Pelican元数据转换为YAML frontmatter，键名统一为小写
+    pub convert_pelican_to_yaml: bool,
+    // 事务模式：任何文件无法写入时，所有文件都保持原样
+    pub transactional: bool,
+    // frontmatter中键的顺序
+    pub key_order: KeyOrder,
+    // YAML frontmatter的写出格式，默认使用 serde_yaml_ng 的格式
+    pub yaml_style: Option<YamlStyle>,
+}
+
+// 根据原文件的格式生成新的frontmatter文本，返回写入时使用的格式
+fn build_frontmatter(
+    document: &Document,
+    data: &AHashIndexMap<String, Value>,
+    options: &WriteOptions,
+) -> Result<(FrontmatterFormat, String), WriteError> {
+    let format = match document.format {
+        Some(format) => format,
+        None if options.insert_missing => options.insert_format,
+        None => {
+            return Err(WriteError::new(
+                WriteErrorKind::MissingFrontmatter,
+                "File has no frontmatter",
+            ));
+        }
+    };
+
+    // 原有的frontmatter无法解析时，多半是被误认为frontmatter的正文（如用作分隔线的 ---），
+    // 覆盖会丢失这些内容，因此不写入
+    if let Some(original_format) = document.format
+        && let Err(e) = parse_frontmatter(original_format, &document.frontmatter)
+    {
+        return Err(WriteError::new(
+            WriteErrorKind::InvalidFrontmatter,
+            format!("Existing frontmatter cannot be parsed: {}", e.message),
+        ));
+    }
+
+    // 日期时间按原始文本写回，TOML保留带标签的值，写为TOML日期时间
+    let mut data = data.clone();
+    if format != FrontmatterFormat::Toml {
+        data.values_mut().for_each(untag_datetimes);
+    }
+    options.key_order.apply(&mut data);
+    let data = &data;
+    let reorder = !options.key_order.is_preserve();
+    let style = options.yaml_style.as_ref();
+
+    let serialized = match format {
+        FrontmatterFormat::Pelican if options.convert_pelican_to_yaml => {
+            let data = data
+                .iter()
+                .map(|(key, value)| (key.to_lowercase(), value.clone()))
+                .collect();
+            serialize_yaml_frontmatter(&data, style)
+                .map(|content| (FrontmatterFormat::Yaml, content))
+        }
+        // 优先在原文本上修改，保留注释、引号风格和键的顺序
+        FrontmatterFormat::Yaml => {
+            match patch_yaml_frontmatter(&document.frontmatter, data, reorder, style) {
+                Some(patched) => Ok(patched),
+                // 重新生成会展开锚点、别名和合并键，不写入该文件
+                None if uses_anchors(&document.frontmatter) => {
+                    return Err(WriteError::new(
+                        WriteErrorKind::CannotPreserveFormatting,
+                        "The change cannot be written without expanding YAML anchors, aliases or merge keys",
+                    ));
+                }
+                None => serialize_yaml_frontmatter(data, style),
+            }
+            .map(|content| (format, content))
+        }
+        FrontmatterFormat::Toml => serialize_toml_frontmatter(&document.frontmatter, data, reorder)
+            .map(|content| (format, content)),
+        FrontmatterFormat::Json => {
+            serialize_json_frontmatter(data, detect_json_indent(&document.frontmatter))
+                .map(|content| (format, content))
+        }
+        FrontmatterFormat::Pelican => {
+            serialize_pelican_metadata(&document.frontmatter, data, reorder)
+                .map(|content| (format, content))
+        }
+    };
+    serialized.map_err(|e| WriteError::new(WriteErrorKind::Serialization, e))
+}
+
+// 文件在调用方读取之后被修改：data 为本次要写入的内容，current 为磁盘上的当前内容
+#[derive(Debug, Serialize)]
+pub struct WriteConflict {
+    data: AHashIndexMap<String, Value>,
+    current: ParsedFile,
+}
+
+// 写入失败或跳过的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteErrorKind {
+    NotFound,
+    PermissionDenied,
+    // 文件没有frontmatter，且没有开启 insert_missing
+    MissingFrontmatter,
+    // 文件开头的frontmatter无法解析，为避免覆盖正文而没有写入
+    InvalidFrontmatter,
+    // 传入了扩展名列表，文件的扩展名不在其中
+    UnlistedExtension,
+    // 文件内容无法解码，或新内容无法用原编码表示
+    Encoding,
+    // 数据无法序列化为文件的frontmatter格式
+    Serialization,
+    // 修改无法在保留YAML锚点、别名和合并键的同时写出，如删除继承的键
+    CannotPreserveFormatting,
+    // 事务模式下其他文件无法写入，此文件未写入或已恢复为原内容
+    TransactionAborted,
+    // 其他读写错误
+    Io,
+}
+
+impl WriteErrorKind {
+    // 扩展名不匹配、没有frontmatter、frontmatter无法解析或无法保留锚点的文件按设计跳过，不算作失败
+    pub fn is_skip(self) -> bool {
+        matches!(
+            self,
+            Self::UnlistedExtension
+                | Self::MissingFrontmatter
+                | Self::InvalidFrontmatter
+                | Self::CannotPreserveFormatting
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct WriteError {
+    pub kind: WriteErrorKind,
+    pub message: String,
+}
+
+impl WriteError {
+    pub fn new(kind: WriteErrorKind, message: impl Display) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Error> for WriteError {
+    fn from(e: Error) -> Self {
+        let kind = match e.kind() {
+            ErrorKind::NotFound => WriteErrorKind::NotFound,
+            ErrorKind::PermissionDenied => WriteErrorKind::PermissionDenied,
+            // 内容无法解码，或新内容无法用目标编码表示
+            ErrorKind::InvalidData => WriteErrorKind::Encoding,
+            _ => WriteErrorKind::Io,
+        };
+        Self::new(kind, e)
+    }
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteStatus {
+    Written,
+    Skipped,
+    Conflict,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WriteResult {
+    pub status: WriteStatus,
+    // 跳过或失败时的原因
+    pub error_kind: Option<WriteErrorKind>,
+    pub message: Option<String>,
+    // 写入成功的文件的新版本，再次保存时传回
+    pub revision: Option<FileRevision>,
+    pub conflict: Option<Box<WriteConflict>>,
+}
+
+impl WriteResult {
+    pub fn written(revision: FileRevision) -> Self {
+        Self {
+            status: WriteStatus::Written,
+            error_kind: None,
+            message: None,
+            revision: Some(revision),
+            conflict: None,
+        }
+    }
+
+    pub fn conflict(data: AHashIndexMap<String, Value>, current: ParsedFile) -> Self {
+        Self {
+            status: WriteStatus::Conflict,
+            error_kind: None,
+            message: Some("File changed on disk since it was read".to_string()),
+            revision: None,
+            conflict: Some(Box::new(WriteConflict { data, current })),
+        }
+    }
+
+    pub fn error(status: WriteStatus, error: WriteError) -> Self {
+        Self {
+            status,
+            error_kind: Some(error.kind),
+            message: Some(error.message),
+            revision: None,
+            conflict: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WriteSummary {
+    pub written: usize,
+    pub skipped: usize,
+    pub conflict: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WriteReport {
+    pub files: AHashIndexMap<String, WriteResult>,
+    pub summary: WriteSummary,
+    // 本次写入在写入记录中的编号，用于撤销；没有修改任何文件或无法记录时为空
+    pub batch: Option<u64>,
+}
+
+impl WriteReport {
+    pub fn from_results(results: Vec<(String, WriteResult)>) -> Self {
+        let mut report = WriteReport::default();
+        for (file_path, result) in results {
+            let count = match result.status {
+                WriteStatus::Written => &mut report.summary.written,
+                WriteStatus::Skipped => &mut report.summary.skipped,
+                WriteStatus::Conflict => &mut report.summary.conflict,
+                WriteStatus::Failed => &mut report.summary.failed,
+            };
+            *count += 1;
+            report.files.insert(file_path, result);
+        }
+        report.files.sort_unstable_keys();
+        report
+    }
+}
+
+pub enum Prepared<T> {
+    Ready(T),
+    // 文件已被修改，附带当前的内容
+    Conflict(Box<ParsedFile>),
+}
+
+// 生成保持原有frontmatter格式和编码的新文件内容，写入和预览共用
+// 只把frontmatter读入内存，正文在输出时从原文件按块复制或重新编码，内存占用与文件大小无关
+pub struct ContentGenerator {
+    file_path: String,
+    file: File,
+    pub document: Document,
+    // 新内容中正文之前的部分
+    header: Vec<u8>,
+    pub encoding: &'static Encoding,
+    // 打开文件时的版本
+    pub revision: FileRevision,
+}
+
+impl ContentGenerator {
+    // 按块检测编码并计算当前版本，传入读取时的版本且文件已被修改时返回冲突
+    pub fn open(
+        file_path: &str,
+        data: &AHashIndexMap<String, Value>,
+        options: &WriteOptions,
+        expected: Option<&FileRevision>,
+    ) -> Result<Prepared<Self>, WriteError> {
+        let mut file = File::open(file_path)?;
+        let modified = file.metadata()?.modified().ok();
+        let mut hasher = Sha256::new();
+        let (encoding, bom_len) = detect_encoding_streamed(&mut file, &mut hasher)?;
+        let revision = FileRevision::new(format!("{:x}", hasher.finalize()), modified);
+        if expected.is_some_and(|expected| !expected.matches(&revision)) {
+            return Ok(Prepared::Conflict(Box::new(read_and_parse_file(file_path))));
+        }
+
+        let target_encoding = if options.convert_to_utf8 {
+            UTF_8
+        } else {
+            encoding
+        };
+        let document = Document::read_head(&mut file, encoding, bom_len)?;
+        let (format, frontmatter_content) = build_frontmatter(&document, data, options)?;
+        let header = document.render_header(format, &frontmatter_content, target_encoding)?;
+        Ok(Prepared::Ready(Self {
+            file_path: file_path.to_owned(),
+            file,
+            document,
+            header,
+            encoding: target_encoding,
+            revision,
+        }))
+    }
+
+    // 新内容写入 output，原文件的全部内容写入 original_sink，返回新内容的SHA-256
+    // 打开之后文件又被修改时，已生成的内容基于旧版本，返回冲突
+    pub fn write_to(
+        &mut self,
+        original_sink: &mut impl Write,
+        output: &mut impl Write,
+    ) -> Result<Prepared<String>, WriteError> {
+        let mut original_hasher = Sha256::new();
+        let mut hasher = Sha256::new();
+        let mut original_sink = Tee(vec![&mut original_hasher, original_sink]);
+        let mut output = Tee(vec![&mut hasher, output]);
+        output.write_all(&self.header)?;
+        self.document.write_body(
+            &mut self.file,
+            self.encoding,
+            &mut original_sink,
+            &mut output,
+        )?;
+
+        let original_sha256 = format!("{:x}", original_hasher.finalize());
+        if self.revision.sha256.as_ref() != Some(&original_sha256) {
+            let current = read_and_parse_file(&self.file_path);
+            return Ok(Prepared::Conflict(Box::new(current)));
+        }
+        Ok(Prepared::Ready(format!("{:x}", hasher.finalize())))
+    }
+
+    // 原内容和新内容开头的文本：原有的开头和新的开头之后都接上 context 行正文，用于预览修改
+    pub fn leading_text(&mut self, context: usize) -> Result<(String, String), Error> {
+        let (head, body) = self.document.read_leading_text(&mut self.file, context)?;
+        let header = self.encoding.decode(&self.header).0;
+        Ok((head + &body, header.into_owned() + &body))
+    }
+}
+
+// 传入扩展名列表时，只写入扩展名匹配的文件
+pub fn check_extension(file_path: &str, extensions: Option<&[String]>) -> Result<(), WriteError> {
+    if extensions.is_none_or(|extensions| has_content_extension(Path::new(file_path), extensions)) {
+        return Ok(());
+    }
+    eprintln!("Skipped file with unlisted extension: {}", file_path);
+    Err(WriteError::new(
+        WriteErrorKind::UnlistedExtension,
+        "File extension is not in the extension list",
+    ))
+}
+
+// 同时写入多个目标，如临时文件、哈希和写入记录
+struct Tee<'a>(Vec<&'a mut dyn Write>);
+
+impl Write for Tee<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        for sink in &mut self.0 {
+            sink.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.0.iter_mut().try_for_each(|sink| sink.flush())
+    }
+}
+
+// 已写入临时文件、等待替换原文件的新内容
+struct StagedWrite {
+    staged: StagedFile,
+    // 新内容的SHA-256
+    sha256: String,
+    entry: Option<JournalEntry>,
+    // 事务模式下原内容的副本，替换后需要回滚时用它恢复
+    backup: Option<StagedFile>,
+}
+
+// 为文件生成新内容并写入临时文件，不替换原文件，同时保存写入记录和事务模式下的原内容副本
+// 传入读取时的版本且文件已被修改时返回冲突
+fn stage_write(
     file_path: &str,
     data: &AHashIndexMap<String, Value>,
-) -> Result<(), Error> {
-    let yaml_content = serialize_yaml_frontmatter(data)?;
-    write_frontmatter(file_path, &yaml_content)
+    options: &WriteOptions,
+    expected: Option<&FileRevision>,
+    journal: Option<&Journal>,
+) -> Result<Prepared<StagedWrite>, WriteError> {
+    let mut generator = match ContentGenerator::open(file_path, data, options, expected)? {
+        Prepared::Ready(generator) => generator,
+        Prepared::Conflict(current) => return Ok(Prepared::Conflict(current)),
+    };
+
+    let path = Path::new(file_path);
+    let mut staged = StagedFile::create(path)?;
+    let mut backup = options
+        .transactional
+        .then(|| StagedFile::create(path))
+        .transpose()?;
+    let mut before = journal.map(Journal::blob_writer).transpose()?;
+    let mut after = journal.map(Journal::blob_writer).transpose()?;
+
+    let mut output = Tee(vec![&mut staged]);
+    output
+        .0
+        .extend(after.as_mut().map(|after| after as &mut dyn Write));
+    let mut original_sink = Tee(Vec::new());
+    original_sink
+        .0
+        .extend(before.as_mut().map(|before| before as &mut dyn Write));
+    original_sink
+        .0
+        .extend(backup.as_mut().map(|backup| backup as &mut dyn Write));
+    let sha256 = match generator.write_to(&mut original_sink, &mut output)? {
+        Prepared::Ready(sha256) => sha256,
+        Prepared::Conflict(current) => return Ok(Prepared::Conflict(current)),
+    };
+
+    staged.finish()?;
+    if let Some(backup) = &mut backup {
+        backup.finish()?;
+    }
+    let entry = match (before, after) {
+        (Some(before), Some(after)) => Some(JournalEntry {
+            before: before.finish()?,
+            after: after.finish()?,
+        }),
+        _ => None,
+    };
+    Ok(Prepared::Ready(StagedWrite {
+        staged,
+        sha256,
+        entry,
+        backup,
+    }))
 }
 
-#[tauri::command]
-pub fn write_multiple_frontmatter(
-    file_data: AHashHashMap<String, AHashIndexMap<String, Value>>,
-) -> AHashIndexMap<String, bool> {
-    file_data
+// 生成文件的新内容并写入临时文件；跳过、冲突或失败时直接返回该文件的结果
+fn prepare_file(
+    file_path: &str,
+    data: &AHashIndexMap<String, Value>,
+    extensions: Option<&[String]>,
+    options: &WriteOptions,
+    expected: Option<&FileRevision>,
+    journal: Option<&Journal>,
+) -> Result<StagedWrite, WriteResult> {
+    let staged = check_extension(file_path, extensions)
+        .and_then(|()| stage_write(file_path, data, options, expected, journal));
+    match staged {
+        Ok(Prepared::Ready(staged)) => Ok(staged),
+        Ok(Prepared::Conflict(current)) => {
+            eprintln!("File changed on disk since it was read: {}", file_path);
+            Err(WriteResult::conflict(data.clone(), *current))
+        }
+        Err(e) if e.kind.is_skip() => Err(WriteResult::error(WriteStatus::Skipped, e)),
+        Err(e) => {
+            eprintln!("Failed to write frontmatter to file {}: {}", file_path, e);
+            Err(WriteResult::error(WriteStatus::Failed, e))
+        }
+    }
+}
+
+// 文件已经被替换，无法读取修改时间时只用新内容的哈希作为版本，写入结果和写入记录照常返回
+pub fn written_revision(file_path: &str, sha256: String) -> FileRevision {
+    let modified = fs::metadata(file_path)
+        .and_then(|metadata| metadata.modified())
+        .inspect_err(|e| {
+            eprintln!(
+                "Failed to read the modification time of written file {}: {}",
+                file_path, e
+            )
+        })
+        .ok();
+    FileRevision::new(sha256, modified)
+}
+
+fn failed(file_path: &str, e: Error) -> WriteResult {
+    eprintln!("Failed to write frontmatter to file {}: {}", file_path, e);
+    WriteResult::error(WriteStatus::Failed, e.into())
+}
+
+type FileOutcome = (String, WriteResult, Option<JournalEntry>);
+
+fn write_file(
+    file_path: &str,
+    data: &AHashIndexMap<String, Value>,
+    extensions: Option<&[String]>,
+    options: &WriteOptions,
+    expected: Option<&FileRevision>,
+    journal: Option<&Journal>,
+    own_writes: &OwnWrites,
+) -> (WriteResult, Option<JournalEntry>) {
+    let write = match prepare_file(file_path, data, extensions, options, expected, journal) {
+        Ok(write) => write,
+        Err(result) => return (result, None),
+    };
+
+    own_writes.expect(file_path, &write.sha256);
+    match write.staged.commit() {
+        Ok(()) => {
+            let revision = written_revision(file_path, write.sha256);
+            (WriteResult::written(revision), write.entry)
+        }
+        Err(e) => (failed(file_path, e), None),
+    }
+}
+
+fn aborted() -> WriteResult {
+    WriteResult::error(
+        WriteStatus::Skipped,
+        WriteError::new(
+            WriteErrorKind::TransactionAborted,
+            "Not written because another file in the batch could not be written",
+        ),
+    )
+}
+
+// 事务模式：先为所有文件生成新内容并写入临时文件，有文件冲突或失败时不替换任何文件；
+// 逐个替换时出错，则将已经替换的文件恢复为原内容，使所有文件保持写入前的状态
+fn write_transaction(
+    file_data: &AHashHashMap<String, AHashIndexMap<String, Value>>,
+    extensions: Option<&[String]>,
+    options: &WriteOptions,
+    revisions: &AHashHashMap<String, FileRevision>,
+    journal: Option<&Journal>,
+    own_writes: &OwnWrites,
+) -> Vec<FileOutcome> {
+    let staged: Vec<(&String, Result<StagedWrite, WriteResult>)> = file_data
         .par_iter()
         .map(|(file_path, frontmatter)| {
-            let success = write_yaml_frontmatter(file_path, frontmatter).is_ok();
-            if !success {
-                eprintln!("Failed to write frontmatter to file: {}", file_path);
+            let expected = revisions.get(file_path);
+            let staged = prepare_file(
+                file_path,
+                frontmatter,
+                extensions,
+                options,
+                expected,
+                journal,
+            );
+            (file_path, staged)
+        })
+        .collect();
+
+    // 按设计跳过的文件不影响其他文件
+    let abort = staged
+        .iter()
+        .any(|(_, staged)| matches!(staged, Err(result) if result.status != WriteStatus::Skipped));
+    if abort {
+        return staged
+            .into_iter()
+            .map(|(file_path, staged)| {
+                (
+                    file_path.clone(),
+                    staged.err().unwrap_or_else(aborted),
+                    None,
+                )
+            })
+            .collect();
+    }
+
+    let mut outcomes = Vec::with_capacity(staged.len());
+    let mut committed = Vec::new();
+    let mut staged = staged.into_iter();
+    let mut failure = None;
+    for (file_path, write) in staged.by_ref() {
+        let write = match write {
+            Ok(write) => write,
+            Err(result) => {
+                outcomes.push((file_path.clone(), result, None));
+                continue;
             }
-            (file_path.clone(), success)
+        };
+        let StagedWrite {
+            staged,
+            sha256,
+            entry,
+            backup,
+            ..
+        } = write;
+        own_writes.expect(file_path, &sha256);
+        match staged.commit() {
+            Ok(()) => committed.push((file_path, sha256, entry, backup)),
+            Err(e) => {
+                failure = Some((file_path, e));
+                break;
+            }
+        }
+    }
+
+    let Some((failed_path, e)) = failure else {
+        outcomes.extend(committed.into_iter().map(|(file_path, sha256, entry, _)| {
+            let revision = written_revision(file_path, sha256);
+            (file_path.clone(), WriteResult::written(revision), entry)
+        }));
+        return outcomes;
+    };
+
+    // 还没有替换的文件在丢弃时删除临时文件
+    outcomes.push((failed_path.clone(), failed(failed_path, e), None));
+    outcomes.extend(staged.map(|(file_path, _)| (file_path.clone(), aborted(), None)));
+    outcomes.extend(
+        committed.into_iter().map(|(file_path, _, _, backup)| {
+            (file_path.clone(), roll_back(file_path, backup), None)
+        }),
+    );
+    outcomes
+}
+
+// 用写入前保存的原内容恢复已经替换的文件，没有保存原内容或恢复失败时作为失败返回
+fn roll_back(file_path: &str, backup: Option<StagedFile>) -> WriteResult {
+    let restored = match backup {
+        Some(backup) => backup.commit(),
+        None => Err(Error::other("Original content was not kept")),
+    };
+    match restored {
+        Ok(()) => aborted(),
+        Err(e) => {
+            eprintln!("Failed to roll back file {}: {}", file_path, e);
+            WriteResult::error(
+                WriteStatus::Failed,
+                WriteError::new(
+                    WriteErrorKind::Io,
+                    format!("Written but could not be rolled back: {}", e),
+                ),
+            )
+        }
+    }
+}
+
+// 传入扩展名列表时，不匹配的文件不会被写入
+// revisions 为调用方读取各文件时得到的版本，没有传入版本的文件直接覆盖
+// 修改过的文件会记录在写入记录中，可以通过返回的批次编号撤销
+#[tauri::command]
+pub fn write_multiple_frontmatter(
+    app: AppHandle,
+    file_data: AHashHashMap<String, AHashIndexMap<String, Value>>,
+    extensions: Option<Vec<String>>,
+    options: Option<WriteOptions>,
+    revisions: Option<AHashHashMap<String, FileRevision>>,
+) -> WriteReport {
+    let options = options.unwrap_or_default();
+    let revisions = revisions.unwrap_or_default();
+    let journal = Journal::open(&app)
+        .inspect_err(|e| {
+            eprintln!(
+                "Write journal is unavailable, writes cannot be undone: {}",
+                e
+            )
         })
-        .collect()
+        .ok();
+    let active = journal.as_ref().map(Journal::begin);
+    let own_writes = app.state::<OwnWrites>();
+
+    let outcomes: Vec<FileOutcome> = if options.transactional {
+        write_transaction(
+            &file_data,
+            extensions.as_deref(),
+            &options,
+            &revisions,
+            journal.as_ref(),
+            &own_writes,
+        )
+    } else {
+        file_data
+            .par_iter()
+            .map(|(file_path, frontmatter)| {
+                let (result, entry) = write_file(
+                    file_path,
+                    frontmatter,
+                    extensions.as_deref(),
+                    &options,
+                    revisions.get(file_path),
+                    journal.as_ref(),
+                    &own_writes,
+                );
+                (file_path.clone(), result, entry)
+            })
+            .collect()
+    };
+    // 内容没有变化的文件不需要记录
+    let mut entries = AHashIndexMap::default();
+    let mut results = Vec::with_capacity(outcomes.len());
+    for (file_path, result, entry) in outcomes {
+        if let Some(entry) = entry.filter(|entry| entry.before != entry.after) {
+            entries.insert(file_path.clone(), entry);
+        }
+        results.push((file_path, result));
+    }
+
+    let mut report = WriteReport::from_results(results);
+    if let (Some(journal), Some(active)) = (&journal, active)
+        && !entries.is_empty()
+    {
+        entries.sort_unstable_keys();
+        match journal.record(entries, active) {
+            Ok(id) => report.batch = Some(id),
+            Err(e) => eprintln!("Failed to record write batch: {}", e),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::OrderPolicy,
+        encoding::{bom_of, encode},
+        parse_file_bytes,
+    };
+    use encoding_rs::{GBK, UTF_16LE};
+
+    fn rewrite(original: &str, data: &AHashIndexMap<String, Value>) -> String {
+        let document = Document::parse(original.as_bytes().to_vec()).unwrap();
+        build_frontmatter(&document, data, &WriteOptions::default())
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn unparseable_frontmatter_is_not_overwritten() {
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("x"))]);
+        let original = "---\nA paragraph between rules.\n---\n\nBody\n";
+        let path = std::env::temp_dir().join("cyrene-test-invalid-rule.md");
+        fs::write(&path, original).unwrap();
+        let file_path = path.to_str().unwrap();
+
+        let options = WriteOptions::default();
+        let (result, _) = write_file(
+            file_path,
+            &data,
+            None,
+            &options,
+            None,
+            None,
+            &OwnWrites::default(),
+        );
+        assert_eq!(result.status, WriteStatus::Skipped);
+        assert_eq!(result.error_kind, Some(WriteErrorKind::InvalidFrontmatter));
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        fs::remove_file(&path).unwrap();
+    }
+
+    // 以短代码或Liquid标签开头的文件没有frontmatter，开启插入模式时在开头插入新的frontmatter
+    #[test]
+    fn template_tags_at_the_start_are_body() {
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("x"))]);
+        for (name, original) in [
+            ("shortcode", "{{< figure src=\"a.png\" >}}\n\nBody\n"),
+            ("liquid", "{% include x.html %}\n\nBody\n"),
+        ] {
+            let path = std::env::temp_dir().join(format!("cyrene-test-template-{}.md", name));
+            fs::write(&path, original).unwrap();
+            let file_path = path.to_str().unwrap();
+
+            let (result, _) = write_file(
+                file_path,
+                &data,
+                None,
+                &Default::default(),
+                None,
+                None,
+                &OwnWrites::default(),
+            );
+            assert_eq!(result.status, WriteStatus::Skipped, "{}", name);
+            assert_eq!(
+                result.error_kind,
+                Some(WriteErrorKind::MissingFrontmatter),
+                "{}",
+                name
+            );
+            assert_eq!(fs::read_to_string(&path).unwrap(), original, "{}", name);
+
+            let options = WriteOptions {
+                insert_missing: true,
+                ..Default::default()
+            };
+            let (result, _) = write_file(
+                file_path,
+                &data,
+                None,
+                &options,
+                None,
+                None,
+                &OwnWrites::default(),
+            );
+            assert_eq!(result.status, WriteStatus::Written, "{}", name);
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                format!("---\ntitle: x\n---\n{}", original),
+                "{}",
+                name
+            );
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    // 删除继承的键无法在保留合并键的同时写出，跳过该文件而不是展开锚点
+    #[test]
+    fn yaml_anchors_are_never_expanded() {
+        let original = "---\ndefaults: &defaults\n  layout: post\n<<: *defaults\ntitle: a\n---\n";
+        let document = Document::parse(original.as_bytes().to_vec()).unwrap();
+        let mut data = parse_file_bytes(original.as_bytes().to_vec()).frontmatter;
+        data.shift_remove("layout");
+        let error = build_frontmatter(&document, &data, &WriteOptions::default()).unwrap_err();
+        assert_eq!(error.kind, WriteErrorKind::CannotPreserveFormatting);
+        assert!(error.kind.is_skip());
+
+        // 没有锚点的frontmatter无法在原文上修改时重新生成
+        let original = "---\n{title: a, tags: [x]}\n---\n";
+        let document = Document::parse(original.as_bytes().to_vec()).unwrap();
+        let mut data = parse_file_bytes(original.as_bytes().to_vec()).frontmatter;
+        data.insert("title".to_owned(), Value::from("b"));
+        assert_eq!(
+            build_frontmatter(&document, &data, &WriteOptions::default())
+                .unwrap()
+                .1,
+            "title: b\ntags:\n- x\n"
+        );
+    }
+
+    // 替换之后读取不到修改时间，文件仍然算作已写入，版本只包含新内容的哈希
+    #[test]
+    fn written_revision_falls_back_to_the_hash() {
+        let revision = written_revision("/nonexistent/cyrene-test.md", "abc".to_owned());
+        assert_eq!(revision.sha256.as_deref(), Some("abc"));
+        assert_eq!(revision.mtime, None);
+    }
+
+    // 事务模式下有文件冲突时，其他文件也不写入
+    #[test]
+    fn transaction_writes_nothing_when_a_file_conflicts() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<String> = ["a", "b"]
+            .iter()
+            .map(|name| {
+                let path = dir.join(format!("cyrene-test-transaction-{}.md", name));
+                fs::write(&path, "---\ntitle: old\n---\nBody\n").unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("new"))]);
+        let file_data: AHashHashMap<String, AHashIndexMap<String, Value>> = paths
+            .iter()
+            .map(|path| (path.clone(), data.clone()))
+            .collect();
+        // 第二个文件在读取之后被修改过
+        let stale = FileRevision::of(b"stale", None);
+        let revisions = AHashHashMap::from_iter([(paths[1].clone(), stale)]);
+        let options = WriteOptions {
+            transactional: true,
+            ..Default::default()
+        };
+
+        let outcomes = write_transaction(
+            &file_data,
+            None,
+            &options,
+            &revisions,
+            None,
+            &OwnWrites::default(),
+        );
+        let status = |path: &String| {
+            let (_, result, _) = outcomes.iter().find(|(p, _, _)| p == path).unwrap();
+            (result.status, result.error_kind)
+        };
+        assert_eq!(
+            status(&paths[0]),
+            (
+                WriteStatus::Skipped,
+                Some(WriteErrorKind::TransactionAborted)
+            )
+        );
+        assert_eq!(status(&paths[1]), (WriteStatus::Conflict, None));
+        for path in &paths {
+            assert_eq!(
+                fs::read_to_string(path).unwrap(),
+                "---\ntitle: old\n---\nBody\n"
+            );
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn replaced_files_are_rolled_back() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("cyrene-test-rollback.md");
+        fs::write(&path, "original").unwrap();
+        let backup = StagedFile::new(&path, b"original").unwrap();
+        fs::write(&path, "written").unwrap();
+
+        let file_path = path.to_str().unwrap();
+        let result = roll_back(file_path, Some(backup));
+        assert_eq!(
+            (result.status, result.error_kind),
+            (
+                WriteStatus::Skipped,
+                Some(WriteErrorKind::TransactionAborted)
+            )
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+
+        // 没有保存原内容时无法恢复，作为失败返回
+        fs::write(&path, "written").unwrap();
+        let result = roll_back(file_path, None);
+        assert_eq!(
+            (result.status, result.error_kind),
+            (WriteStatus::Failed, Some(WriteErrorKind::Io))
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "written");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_are_written_back_in_their_encoding() {
+        let body = "这是一篇使用GBK编码保存的中文文章，正文足够长以便检测编码。\n";
+        let path = std::env::temp_dir().join("cyrene-test-gbk-write.md");
+        let original = format!("---\ntitle: 旧标题\n---\n{}", body);
+        fs::write(&path, encode(&original, GBK).unwrap()).unwrap();
+        let file_path = path.to_str().unwrap();
+
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("新标题"))]);
+        let (result, _) = write_file(
+            file_path,
+            &data,
+            None,
+            &WriteOptions::default(),
+            None,
+            None,
+            &OwnWrites::default(),
+        );
+        assert_eq!(result.status, WriteStatus::Written);
+        let expected = format!("---\ntitle: 新标题\n---\n{}", body);
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            encode(&expected, GBK).unwrap().as_ref()
+        );
+
+        // GBK无法表示的字符不会被替换后写入
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("标题 😀"))]);
+        let (result, _) = write_file(
+            file_path,
+            &data,
+            None,
+            &WriteOptions::default(),
+            None,
+            None,
+            &OwnWrites::default(),
+        );
+        assert_eq!(
+            (result.status, result.error_kind),
+            (WriteStatus::Failed, Some(WriteErrorKind::Encoding))
+        );
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            encode(&expected, GBK).unwrap().as_ref()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn json_frontmatter_keeps_its_indent() {
+        let original = "{\n    \"title\": \"a\",\n    \"tags\": [\"x\"]\n}\n\nBody\n";
+        let mut data = parse_file_bytes(original.as_bytes().to_vec()).frontmatter;
+        data.insert("title".to_owned(), Value::from("b"));
+        assert_eq!(
+            rewrite(original, &data),
+            "{\n    \"title\": \"b\",\n    \"tags\": [\n        \"x\"\n    ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn pelican_values_are_written_as_strings() {
+        let original = "Title:   Spaced\nTags: a, b\n\nBody\n";
+        let mut data = parse_file_bytes(original.as_bytes().to_vec()).frontmatter;
+        data.insert(
+            "Tags".to_owned(),
+            Value::Sequence(vec![Value::from("c"), Value::from("d")]),
+        );
+        data.insert("Summary".to_owned(), Value::from("first\n\nsecond"));
+        data.insert("Count".to_owned(), Value::from(3));
+        // 值未变化的项保持原样
+        assert_eq!(
+            rewrite(original, &data),
+            "Title:   Spaced\nTags: c, d\nSummary: first\n    second\nCount: 3\n"
+        );
+
+        let document = Document::parse(original.as_bytes().to_vec()).unwrap();
+        let options = WriteOptions::default();
+        let mut nested = data.clone();
+        nested.insert(
+            "Params".to_owned(),
+            serde_yaml_ng::from_str("{x: 1}").unwrap(),
+        );
+        assert!(build_frontmatter(&document, &nested, &options).is_err());
+        data.shift_remove("Title");
+        assert!(build_frontmatter(&document, &data, &options).is_err());
+    }
+
+    #[test]
+    fn toml_strings_and_datetimes_keep_their_types() {
+        let original = "+++\nslug = \"2023-05-01\"\ndate = 2023-05-01T12:00:00+08:00\n+++\nbody\n";
+        let parsed = parse_file_bytes(original.as_bytes().to_vec());
+        assert_eq!(
+            parsed.frontmatter["slug"],
+            Value::String("2023-05-01".to_owned())
+        );
+        assert!(DateTimeValue::from_value(&parsed.frontmatter["date"]).is_some());
+
+        let content = rewrite(original, &parsed.frontmatter);
+        assert_eq!(
+            content,
+            "slug = \"2023-05-01\"\ndate = 2023-05-01T12:00:00+08:00\n"
+        );
+    }
+
+    // 没有修改的TOML保持原样，修改时只替换该键的值，注释和引号风格不变
+    #[test]
+    fn toml_comments_and_quotes_are_kept() {
+        let original = "+++\n# c\ntitle = 'a'  # 标题\ndraft = true\n+++\nBody\n";
+        let mut data = parse_file_bytes(original.as_bytes().to_vec()).frontmatter;
+        assert_eq!(
+            rewrite(original, &data),
+            "# c\ntitle = 'a'  # 标题\ndraft = true\n"
+        );
+        data.insert("draft".to_owned(), Value::from(false));
+        assert_eq!(
+            rewrite(original, &data),
+            "# c\ntitle = 'a'  # 标题\ndraft = false\n"
+        );
+    }
+
+    #[test]
+    fn toml_datetimes_from_the_frontend_are_written_as_datetimes() {
+        let original = "+++\ntitle = \"a\"\n+++\n";
+        let data: AHashIndexMap<String, Value> =
+            serde_json::from_str(r#"{"title": "2024-01-02", "date": {"!datetime": "2024-01-02"}}"#)
+                .unwrap();
+        assert_eq!(
+            rewrite(original, &data),
+            "title = \"2024-01-02\"\ndate = 2024-01-02\n"
+        );
+    }
+
+    // 正文跨越多个读取块，多字节字符被块的边界截断时，流式生成的内容与整体转换的结果一致
+    #[test]
+    fn streamed_content_matches_buffered_conversion() {
+        let body = "正文 body\n".repeat(STREAM_BUFFER_SIZE / 5);
+        let data = AHashIndexMap::from_iter([("title".to_owned(), Value::from("新标题"))]);
+        let bytes = |encoding, bom: bool, text: &str| {
+            let mut bytes = if bom {
+                bom_of(encoding).to_vec()
+            } else {
+                Vec::new()
+            };
+            bytes.extend_from_slice(&encode(text, encoding).unwrap());
+            bytes
+        };
+
+        for (name, encoding, bom, convert_to_utf8) in [
+            ("utf8", UTF_8, false, false),
+            ("utf8-bom", UTF_8, true, false),
+            ("gbk", GBK, false, false),
+            ("gbk-to-utf8", GBK, false, true),
+            ("utf16", UTF_16LE, true, false),
+            ("utf16-to-utf8", UTF_16LE, true, true),
+        ] {
+            let path = std::env::temp_dir().join(format!("cyrene-test-stream-{}.md", name));
+            let original = format!("---\r\ntitle: old\r\n---\r\n{}", body);
+            fs::write(&path, bytes(encoding, bom, &original)).unwrap();
+
+            let options = WriteOptions {
+                convert_to_utf8,
+                ..Default::default()
+            };
+            let Ok(Prepared::Ready(mut generator)) =
+                ContentGenerator::open(path.to_str().unwrap(), &data, &options, None)
+            else {
+                panic!("{}", name);
+            };
+            let mut output = Vec::new();
+            let written = generator.write_to(&mut std::io::sink(), &mut output);
+            assert!(matches!(written, Ok(Prepared::Ready(_))), "{}", name);
+
+            let target = if convert_to_utf8 { UTF_8 } else { encoding };
+            let expected = format!("---\r\ntitle: 新标题\r\n---\r\n{}", body);
+            assert!(
+                output == bytes(target, bom && target == encoding, &expected),
+                "{}",
+                name
+            );
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn pelican_metadata_follows_the_key_order() {
+        let original = "Title: A\nDate: 2023-05-01\nAuthor: me\n    and you\n\nBody\n";
+        let document = Document::parse(original.as_bytes().to_vec()).unwrap();
+        let mut data = parse_file_bytes(original.as_bytes().to_vec()).frontmatter;
+        data.insert("Category".to_owned(), Value::from("news"));
+
+        let content = |key_order| {
+            let options = WriteOptions {
+                key_order,
+                ..Default::default()
+            };
+            build_frontmatter(&document, &data, &options).unwrap().1
+        };
+        assert_eq!(
+            content(KeyOrder::default()),
+            "Title: A\nDate: 2023-05-01\nAuthor: me\n    and you\nCategory: news\n"
+        );
+        assert_eq!(
+            content(KeyOrder::Policy(OrderPolicy::Alphabetical)),
+            "Author: me\n    and you\nCategory: news\nDate: 2023-05-01\nTitle: A\n"
+        );
+    }
 }
@@ -1,6 +1,13 @@
-use crate::{constants::*, write};
+use crate::{
+    config::ProjectConfig,
+    constants::*,
+    datetime::{DateTimeValue, untag_datetimes},
+    preview::{PreviewReport, preview_write},
+    write::{self, WriteReport},
+};
 use serde_yaml_ng::Value;
 use std::{borrow::Cow, collections::HashMap, path::Path};
+use tauri::AppHandle;
 use umya_spreadsheet::*;
 
 use indexmap::{IndexMap, IndexSet};
@@ -50,9 +57,22 @@ pub fn export_frontmatter_to_xlsx(
         // 为每个文件填充该字段的值
         for (file_path, frontmatter) in &data {
             if let Some(&col) = path_to_col.get(file_path) {
+                // 日期时间写为Excel日期单元格
+                if let Some(datetime) = frontmatter.get(key).and_then(DateTimeValue::from_value) {
+                    let cell = worksheet.get_cell_mut((row_index, col));
+                    cell.set_value_number(datetime.to_excel_serial());
+                    cell.get_style_mut()
+                        .get_number_format_mut()
+                        .set_format_code(excel_date_format(&datetime));
+                    continue;
+                }
+
                 let value_str = if let Some(value) = frontmatter.get(key) {
+                    // 嵌套在数组和对象中的日期时间按原始文本输出
+                    let mut value = value.clone();
+                    untag_datetimes(&mut value);
                     // 将Value转换为字符串
-                    match value {
+                    match &value {
                         Value::String(s) => s.clone(),
                         Value::Number(n) => n.to_string(),
                         Value::Bool(b) => b.to_string(),
@@ -69,7 +89,7 @@ pub fn export_frontmatter_to_xlsx(
                                 .unwrap_or_else(|_| EMPTY_YAML_OBJECT.to_string())
                         }
                         Value::Null => String::new(),
-                        _ => format!("{:?}", value),
+                        Value::Tagged(tagged) => value_to_string(&tagged.value).into_owned(),
                     }
                 } else {
                     String::new() // 如果该文件没有这个字段，则为空
@@ -91,13 +111,13 @@ pub fn export_frontmatter_to_xlsx(
     }
 }
 
-#[tauri::command]
-pub fn import_frontmatter_from_xlsx(
-    xlsx_path: String,
-    base_path: String,
-) -> Result<String, String> {
+// 读取表格中每个文件的frontmatter
+fn read_xlsx_file_data(
+    xlsx_path: &str,
+    base_path: &str,
+) -> Result<AHashHashMap<String, AHashIndexMap<String, Value>>, String> {
     // 读取XLSX文件
-    let book = match reader::xlsx::read(&xlsx_path) {
+    let book = match reader::xlsx::read(xlsx_path) {
         Ok(book) => book,
         Err(e) => return Err(format!("{}{}", ERROR_EXCEL_READ, e)),
     };
@@ -118,10 +138,10 @@ pub fn import_frontmatter_from_xlsx(
                 let full_path = if path_str.starts_with('/') || path_str.starts_with('\\') {
                     // 如果路径以 / 或 \ 开头，去掉开头的分隔符，作为相对路径处理
                     let relative_path = path_str.trim_start_matches(['/', '\\']);
-                    Path::new(&base_path).join(relative_path)
+                    Path::new(base_path).join(relative_path)
                 } else {
                     // 直接拼接相对路径
-                    Path::new(&base_path).join(&path_str)
+                    Path::new(base_path).join(&path_str)
                 };
                 file_paths.push(full_path.to_string_lossy().into_owned());
             } else {
@@ -158,12 +178,27 @@ pub fn import_frontmatter_from_xlsx(
 
     for (col_idx, file_path) in file_paths.iter().enumerate() {
         let mut frontmatter: AHashIndexMap<String, Value> = AHashIndexMap::default();
+        // 文件中现有的frontmatter，只在遇到日期单元格时读取
+        let mut existing: Option<AHashIndexMap<String, Value>> = None;
 
         for (row_idx, field_name) in field_names.iter().enumerate() {
             let actual_col = (col_idx + 2) as u32; // 从第2列开始
             let actual_row = (row_idx + 2) as u32; // 从第2行开始
 
             if let Some(cell) = worksheet.get_cell((actual_row, actual_col)) {
+                // 日期单元格读取为日期时间；与原值表示同一时间时沿用原值，保留时区和精度
+                if let Some(datetime) = read_date_cell(cell) {
+                    let existing = existing
+                        .get_or_insert_with(|| crate::read_and_parse_file(file_path).frontmatter);
+                    let datetime = existing
+                        .get(field_name)
+                        .and_then(DateTimeValue::from_value)
+                        .filter(|original| original.same_local_time(&datetime))
+                        .unwrap_or(datetime);
+                    frontmatter.insert(field_name.clone(), datetime.into_value());
+                    continue;
+                }
+
                 let cell_value = cell.get_value();
                 let value_str = cell_value.to_string();
                 if !value_str.is_empty() {
@@ -179,22 +214,98 @@ pub fn import_frontmatter_from_xlsx(
         }
     }
 
-    // 使用write函数批量写入
-    let write_results = write::write_multiple_frontmatter(file_data);
+    Ok(file_data)
+}
+
+// 表格中有数据但原文件没有frontmatter时，只有用户确认后才插入新的frontmatter，否则跳过
+fn import_write_options(config: &ProjectConfig, insert_missing: bool) -> write::WriteOptions {
+    write::WriteOptions {
+        convert_to_utf8: config.convert_to_utf8,
+        insert_missing,
+        transactional: config.transactional_writes,
+        key_order: config.key_order.clone(),
+        yaml_style: config.yaml_style.clone(),
+        ..Default::default()
+    }
+}
+
+#[tauri::command]
+pub fn import_frontmatter_from_xlsx(
+    app: AppHandle,
+    xlsx_path: String,
+    base_path: String,
+    insert_missing: bool,
+) -> Result<WriteReport, String> {
+    let config = ProjectConfig::load(Path::new(&base_path))?;
+    let file_data = read_xlsx_file_data(&xlsx_path, &base_path)?;
+
+    // 使用write函数批量写入，返回每个文件的写入结果
+    let options = import_write_options(&config, insert_missing);
+    Ok(write::write_multiple_frontmatter(
+        app,
+        file_data,
+        Some(config.extensions),
+        Some(options),
+        None,
+    ))
+}
+
+// 预览从XLSX导入将要做出的修改，不写入任何文件
+#[tauri::command]
+pub fn preview_import_from_xlsx(
+    xlsx_path: String,
+    base_path: String,
+    insert_missing: bool,
+) -> Result<PreviewReport, String> {
+    let config = ProjectConfig::load(Path::new(&base_path))?;
+    let file_data = read_xlsx_file_data(&xlsx_path, &base_path)?;
+    Ok(preview_write(
+        &file_data,
+        Some(&config.extensions),
+        &import_write_options(&config, insert_missing),
+        &AHashHashMap::default(),
+    ))
+}
+
+// 按原值的精度选择单元格的日期格式
+fn excel_date_format(datetime: &DateTimeValue) -> &'static str {
+    match (datetime.has_date(), datetime.has_time()) {
+        (true, true) => EXCEL_DATETIME_FORMAT,
+        (false, true) => EXCEL_TIME_FORMAT,
+        _ => EXCEL_DATE_FORMAT,
+    }
+}
 
-    // 统计结果
-    let total_files = write_results.len();
-    let successful_files = write_results.values().filter(|&&success| success).count();
-    let failed_files = total_files - successful_files;
+// 判断数字格式是否为日期时间格式，返回是否包含日期部分和时间部分
+fn date_format_parts(format_code: &str) -> Option<(bool, bool)> {
+    // 去掉引号中的文本和方括号中的颜色、区域设置
+    let mut code = String::with_capacity(format_code.len());
+    let mut in_quote = false;
+    let mut in_bracket = false;
+    for c in format_code.chars() {
+        match c {
+            '"' => in_quote = !in_quote,
+            '[' if !in_quote => in_bracket = true,
+            ']' if !in_quote => in_bracket = false,
+            _ if !in_quote && !in_bracket => code.push(c.to_ascii_lowercase()),
+            _ => {}
+        }
+    }
 
-    if failed_files > 0 {
-        Ok(format!(
-            "导入完成：成功 {} 个文件，失败 {} 个文件",
-            successful_files, failed_files
-        ))
-    } else {
-        Ok(format!("导入完成：成功处理 {} 个文件", successful_files))
+    let has_date = code.contains(['y', 'd']);
+    let has_time = code.contains(['h', 's']);
+    if !has_date && !has_time && !code.contains('m') {
+        return None;
     }
+    Some((has_date || !has_time, has_time))
+}
+
+// 辅助函数：读取日期格式的数字单元格
+fn read_date_cell(cell: &Cell) -> Option<DateTimeValue> {
+    let serial = cell.get_value_number()?;
+    let format_code = cell.get_style().get_number_format()?.get_format_code();
+    let (has_date, has_time) = date_format_parts(format_code)?;
+    DateTimeValue::from_excel_serial(serial, has_date, has_time)
 }
 
 // 辅助函数：解析单元格值为合适的YAML值类型
@@ -243,6 +354,7 @@ fn value_to_string(value: &Value) -> Cow<str> {
         Value::Bool(false) => Cow::Borrowed("false"),
         Value::Null => Cow::Borrowed(""),
         Value::Number(n) => Cow::Owned(n.to_string()),
+        Value::Tagged(tagged) => value_to_string(&tagged.value),
         _ => Cow::Owned(format!("{:?}", value)),
     }
 }
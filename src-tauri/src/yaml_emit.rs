@@ -0,0 +1,413 @@
+use crate::constants::*;
+use serde::{Deserialize, Serialize};
+use serde_yaml_ng::Value;
+
+use indexmap::IndexMap;
+
+// 使用ahash作为哈希器的类型别名
+type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
+
+// 字符串必须加引号时使用的引号，无法用单引号表示的内容始终使用双引号
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteStyle {
+    #[default]
+    Single,
+    Double,
+}
+
+// 写出YAML frontmatter时使用的格式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YamlStyle {
+    // 每一级缩进的空格数
+    pub indent: usize,
+    pub quote: QuoteStyle,
+    // 元素都是单行标量、且放得下一行的序列写为 [a, b]
+    pub flow_sequences: bool,
+    // 一行的最大宽度，决定序列能否写在一行内，以及长文本的折行位置
+    pub line_width: usize,
+    // 多行文本写为 | 块，超过一行宽度的长文本写为 > 块
+    pub block_scalars: bool,
+}
+
+impl Default for YamlStyle {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            quote: QuoteStyle::Single,
+            flow_sequences: true,
+            line_width: 80,
+            block_scalars: true,
+        }
+    }
+}
+
+// 开头为这些字符的字符串在YAML中有特殊含义，必须加引号
+const INDICATOR_CHARS: &[char] = &[
+    '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+];
+
+fn is_printable(c: char) -> bool {
+    !c.is_control() && !matches!(c, '\u{2028}' | '\u{2029}' | UTF8_BOM)
+}
+
+// 不加引号时读回的仍是同一个字符串；flow 为 true 时还不能包含 [a, b] 中的分隔符
+fn is_plain_safe(s: &str, flow: bool) -> bool {
+    !s.is_empty()
+        && s.trim() == s
+        && s.chars().all(is_printable)
+        && !s.starts_with(INDICATOR_CHARS)
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.ends_with(':')
+        && !(flow && s.contains(FLOW_INDICATOR_CHARS))
+        && !YAML_AMBIGUOUS_PLAIN.contains(&s)
+        && serde_yaml_ng::from_str::<Value>(s).ok() == Some(Value::String(s.to_owned()))
+}
+
+fn single_quoted(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn double_quoted(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            c if !is_printable(c) => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct Emitter<'a> {
+    style: &'a YamlStyle,
+    out: String,
+}
+
+impl Emitter<'_> {
+    // 缩进至少一个空格，否则无法区分层级
+    fn indent(&self) -> usize {
+        self.style.indent.max(1)
+    }
+
+    fn pad(&mut self, column: usize) {
+        self.out.extend(std::iter::repeat_n(' ', column));
+    }
+
+    // 当前行已经写入的长度
+    fn line_len(&self) -> usize {
+        self.out.len() - self.out.rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn end_line(&mut self) {
+        if !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    // 单行的标量，不支持的值（如带标签的值）返回 None
+    fn inline_scalar(&self, value: &Value, flow: bool) -> Option<String> {
+        match value {
+            Value::String(s) if is_plain_safe(s, flow) => Some(s.clone()),
+            Value::String(s) => Some(match self.style.quote {
+                QuoteStyle::Single if s.chars().all(|c| c == '\t' || is_printable(c)) => {
+                    single_quoted(s)
+                }
+                _ => double_quoted(s),
+            }),
+            Value::Null | Value::Bool(_) | Value::Number(_) => {
+                Some(serde_yaml_ng::to_string(value).ok()?.trim_end().to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    fn flow_sequence(&self, items: &[Value]) -> Option<String> {
+        let items = items
+            .iter()
+            .map(|item| self.inline_scalar(item, true))
+            .collect::<Option<Vec<_>>>()?;
+        Some(format!("[{}]", items.join(COMMA_SEPARATOR)))
+    }
+
+    // 多行文本写为 | 块，末尾的换行由 -、+ 标记
+    fn literal_block(&mut self, s: &str, column: usize) -> bool {
+        let body = s.trim_end_matches('\n');
+        // 第一行有缩进时需要缩进标记，此时改用引号
+        if body.trim_start_matches('\n').starts_with(' ')
+            || body.is_empty()
+            || !body
+                .chars()
+                .all(|c| c == '\n' || c == '\t' || is_printable(c))
+        {
+            return false;
+        }
+
+        let chomping = match s.len() - body.len() {
+            0 => "-",
+            1 => "",
+            _ => "+",
+        };
+        self.out.push('|');
+        self.out.push_str(chomping);
+        self.out.push('\n');
+        for line in body.split('\n') {
+            if !line.is_empty() {
+                self.pad(column);
+                self.out.push_str(line);
+            }
+            self.out.push('\n');
+        }
+        for _ in 1..s.len() - body.len() {
+            self.out.push('\n');
+        }
+        true
+    }
+
+    // 超过一行宽度的长文本写为 > 块，在空格处折行，读回时折行处还原为空格
+    fn folded_block(&mut self, s: &str, column: usize) -> bool {
+        if s.trim() != s
+            || s.contains("  ")
+            || !s.contains(' ')
+            || !s.chars().all(is_printable)
+            || self.line_len() + s.len() < self.style.line_width
+        {
+            return false;
+        }
+
+        let width = self.style.line_width.saturating_sub(column).max(20);
+        self.out.push_str(">-\n");
+        let mut line_len = 0;
+        for word in s.split(' ') {
+            if line_len > 0 && line_len + 1 + word.len() > width {
+                self.out.push('\n');
+                line_len = 0;
+            }
+            if line_len == 0 {
+                self.pad(column);
+            } else {
+                self.out.push(' ');
+                line_len += 1;
+            }
+            self.out.push_str(word);
+            line_len += word.len();
+        }
+        self.out.push('\n');
+        true
+    }
+
+    // 写在键或 - 之后的标量，块的内容从 column 列开始
+    fn scalar(&mut self, value: &Value, column: usize) -> Option<()> {
+        if let Value::String(s) = value
+            && self.style.block_scalars
+        {
+            let written = if s.contains('\n') {
+                self.literal_block(s, column)
+            } else {
+                self.folded_block(s, column)
+            };
+            if written {
+                return Some(());
+            }
+        }
+
+        let scalar = self.inline_scalar(value, false)?;
+        self.out.push_str(&scalar);
+        self.out.push('\n');
+        Some(())
+    }
+
+    // 写在键或 - 之后的值，当前位置紧接在 : 或 - 之后
+    fn node(&mut self, value: &Value, column: usize) -> Option<()> {
+        match value {
+            Value::Mapping(mapping) if mapping.is_empty() => self.out.push_str(" {}\n"),
+            Value::Sequence(items) if items.is_empty() => self.out.push_str(" []\n"),
+            Value::Mapping(mapping) => {
+                self.out.push('\n');
+                self.mapping(mapping.iter(), column + self.indent(), false)?;
+            }
+            Value::Sequence(items) => {
+                let flow = self
+                    .style
+                    .flow_sequences
+                    .then(|| self.flow_sequence(items))
+                    .flatten()
+                    .filter(|flow| self.line_len() + 1 + flow.len() <= self.style.line_width);
+                match flow {
+                    Some(flow) => {
+                        self.out.push(' ');
+                        self.out.push_str(&flow);
+                        self.out.push('\n');
+                    }
+                    None => {
+                        self.out.push('\n');
+                        self.sequence(items, column + self.indent())?;
+                    }
+                }
+            }
+            Value::Tagged(_) => return None,
+            _ => {
+                self.out.push(' ');
+                self.scalar(value, column + self.indent())?;
+            }
+        }
+        Some(())
+    }
+
+    // inline_first 为 true 时第一个键紧接在序列的 - 之后，不需要缩进
+    fn mapping<'v>(
+        &mut self,
+        entries: impl Iterator<Item = (&'v Value, &'v Value)>,
+        column: usize,
+        inline_first: bool,
+    ) -> Option<()> {
+        for (index, (key, value)) in entries.enumerate() {
+            if index > 0 || !inline_first {
+                self.pad(column);
+            }
+            let key = self.inline_scalar(key, false)?;
+            self.out.push_str(&key);
+            self.out.push(':');
+            self.node(value, column)?;
+        }
+        Some(())
+    }
+
+    fn sequence(&mut self, items: &[Value], column: usize) -> Option<()> {
+        for item in items {
+            self.pad(column);
+            self.out.push('-');
+            match item {
+                Value::Mapping(mapping) if !mapping.is_empty() => {
+                    self.out.push(' ');
+                    self.mapping(mapping.iter(), column + 2, true)?;
+                }
+                _ => self.node(item, column)?,
+            }
+            self.end_line();
+        }
+        Some(())
+    }
+}
+
+// 按指定的格式写出frontmatter，写出的内容读回后必须与 data 一致，否则返回 None
+pub fn emit_yaml(data: &AHashIndexMap<String, Value>, style: &YamlStyle) -> Option<String> {
+    let keys: Vec<Value> = data.keys().cloned().map(Value::String).collect();
+    let mut emitter = Emitter {
+        style,
+        out: String::new(),
+    };
+    emitter.mapping(keys.iter().zip(data.values()), 0, false)?;
+
+    let emitted = serde_yaml_ng::from_str::<AHashIndexMap<String, Value>>(&emitter.out).ok()?;
+    (emitted == *data).then_some(emitter.out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_of(yaml: &str) -> AHashIndexMap<String, Value> {
+        serde_yaml_ng::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn quotes_are_added_only_when_needed() {
+        let data = data_of(
+            "plain: hello world\ncolon: 'a: b'\napostrophe: \"it's: here\"\nnumber: '42'\nanswer: 'yes'\n",
+        );
+        assert_eq!(
+            emit_yaml(&data, &YamlStyle::default()).unwrap(),
+            "plain: hello world\ncolon: 'a: b'\napostrophe: 'it''s: here'\nnumber: '42'\nanswer: 'yes'\n"
+        );
+
+        let style = YamlStyle {
+            quote: QuoteStyle::Double,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit_yaml(&data, &style).unwrap(),
+            "plain: hello world\ncolon: \"a: b\"\napostrophe: \"it's: here\"\nnumber: \"42\"\nanswer: \"yes\"\n"
+        );
+    }
+
+    #[test]
+    fn sequences_follow_the_indent_and_flow_settings() {
+        let data = data_of(
+            "tags: [a, b]\nparams:\n  authors: [me, you]\n  links:\n  - url: x\n    rel: z\n",
+        );
+        assert_eq!(
+            emit_yaml(&data, &YamlStyle::default()).unwrap(),
+            "tags: [a, b]\nparams:\n  authors: [me, you]\n  links:\n    - url: x\n      rel: z\n"
+        );
+
+        let style = YamlStyle {
+            indent: 4,
+            flow_sequences: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit_yaml(&data, &style).unwrap(),
+            "tags:\n    - a\n    - b\nparams:\n    authors:\n        - me\n        - you\n    links:\n        - url: x\n          rel: z\n"
+        );
+
+        // 放不下一行的序列写为块
+        let style = YamlStyle {
+            line_width: 12,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit_yaml(&data_of("tags: [alpha, beta]\n"), &style).unwrap(),
+            "tags:\n  - alpha\n  - beta\n"
+        );
+    }
+
+    #[test]
+    fn long_and_multiline_text_use_block_scalars() {
+        let long = "word ".repeat(20).trim_end().to_owned();
+        let mut data = AHashIndexMap::default();
+        data.insert("strip".to_owned(), Value::from("a\nb"));
+        data.insert("clip".to_owned(), Value::from("a\nb\n"));
+        data.insert("keep".to_owned(), Value::from("a\nb\n\n"));
+        data.insert("long".to_owned(), Value::from(long.as_str()));
+
+        let style = YamlStyle {
+            line_width: 40,
+            ..Default::default()
+        };
+        let folded = "word ".repeat(7).trim_end().to_owned();
+        let last = "word ".repeat(6).trim_end().to_owned();
+        assert_eq!(
+            emit_yaml(&data, &style).unwrap(),
+            format!(
+                "strip: |-\n  a\n  b\nclip: |\n  a\n  b\nkeep: |+\n  a\n  b\n\nlong: >-\n  {folded}\n  {folded}\n  {last}\n"
+            )
+        );
+
+        let style = YamlStyle {
+            line_width: 40,
+            block_scalars: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit_yaml(&data, &style).unwrap(),
+            format!("strip: \"a\\nb\"\nclip: \"a\\nb\\n\"\nkeep: \"a\\nb\\n\\n\"\nlong: {long}\n")
+        );
+    }
+
+    #[test]
+    fn tagged_values_are_left_to_the_default_serializer() {
+        let data = data_of("date: !datetime 2023-05-01\n");
+        assert_eq!(emit_yaml(&data, &YamlStyle::default()), None);
+    }
+}
@@ -0,0 +1,529 @@
+use crate::{
+    constants::*,
+    yaml_emit::{YamlStyle, emit_yaml},
+};
+use serde_yaml_ng::Value;
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+
+// 使用ahash作为哈希器的类型别名
+type AHashHashSet<T> = HashSet<T, ahash::RandomState>;
+type AHashIndexMap<K, V> = IndexMap<K, V, ahash::RandomState>;
+
+// 原YAML中一个顶层键对应的原始文本，包含紧贴在其上方的注释
+struct KeySegment {
+    key: String,
+    text: String,
+}
+
+// 按顶层键切分后的YAML文本
+struct SplitYaml {
+    leading: String,
+    segments: Vec<KeySegment>,
+}
+
+// 顶层键所在的行：从第0列开始，且不是注释、序列项或空行
+fn is_top_level_key_line(line: &str) -> bool {
+    !line.is_empty() && !line.starts_with([' ', '\t', '#', '-', '\r', '\n'])
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+// 将连续的空行和注释分为两部分：最后一个空行及之前的部分，和紧贴下一个键的注释
+fn split_pending(pending: &str) -> (&str, &str) {
+    let mut offset = 0;
+    let mut split_at = 0;
+    for line in pending.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim().is_empty() {
+            split_at = offset;
+        }
+    }
+    pending.split_at(split_at)
+}
+
+fn split_top_level_keys(original: &str) -> Option<SplitYaml> {
+    let mut leading = String::new();
+    let mut segments: Vec<String> = Vec::new();
+    let mut pending = String::new();
+
+    for line in original.split_inclusive('\n') {
+        if is_top_level_key_line(line) {
+            let (before, attached) = split_pending(&pending);
+            match segments.last_mut() {
+                Some(text) => text.push_str(before),
+                None => leading.push_str(before),
+            }
+            segments.push(format!("{}{}", attached, line));
+            pending.clear();
+        } else if is_blank_or_comment(line) {
+            pending.push_str(line);
+        } else {
+            // 缩进的内容或第0列的序列项，属于当前键的值
+            let text = segments.last_mut()?;
+            text.push_str(&pending);
+            text.push_str(line);
+            pending.clear();
+        }
+    }
+
+    match segments.last_mut() {
+        Some(text) => text.push_str(&pending),
+        None => leading.push_str(&pending),
+    }
+
+    // 每一段必须恰好增加一个键，否则无法安全地保留格式
+    // 别名只能引用前面定义的锚点，因此逐段累加解析而不是单独解析每一段
+    let mut prefix = String::new();
+    let segments = segments
+        .into_iter()
+        .enumerate()
+        .map(|(index, text)| {
+            prefix.push_str(&text);
+            let parsed = serde_yaml_ng::from_str::<AHashIndexMap<String, Value>>(&prefix).ok()?;
+            if parsed.len() != index + 1 {
+                return None;
+            }
+            let key = parsed.into_keys().next_back()?;
+            Some(KeySegment { key, text })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(SplitYaml { leading, segments })
+}
+
+fn serialize_entry(key: &str, value: &Value, style: Option<&YamlStyle>) -> Option<String> {
+    // 指定的格式无法保证读回一致时（如带标签的值）使用默认格式
+    if let Some(style) = style {
+        let mut entry = AHashIndexMap::default();
+        entry.insert(key.to_owned(), value.clone());
+        if let Some(yaml) = emit_yaml(&entry, style) {
+            return Some(yaml);
+        }
+    }
+
+    let mut entry: AHashIndexMap<&str, &Value> = AHashIndexMap::default();
+    entry.insert(key, value);
+    serde_yaml_ng::to_string(&entry).ok()
+}
+
+// 键所在行中键之后的文本：引号中的键跳过引号内的内容，普通的键在第一个后面跟着空白的 : 处结束
+fn after_key(key_line: &str) -> Option<&str> {
+    let rest = match key_line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let mut chars = key_line.char_indices().skip(1);
+            let end = loop {
+                match chars.next()? {
+                    // 双引号中的 \ 转义下一个字符，单引号中的 '' 表示一个引号
+                    (_, '\\') if quote == '"' => {
+                        chars.next();
+                    }
+                    (index, c) if c == quote => {
+                        if quote == '\'' && key_line[index + 1..].starts_with('\'') {
+                            chars.next();
+                        } else {
+                            break index + 1;
+                        }
+                    }
+                    _ => {}
+                }
+            };
+            key_line[end..].trim_start().strip_prefix(':')?
+        }
+        _ => {
+            let colon = key_line
+                .match_indices(':')
+                .map(|(index, _)| index)
+                .find(|&index| {
+                    key_line[index + 1..]
+                        .chars()
+                        .next()
+                        .is_none_or(char::is_whitespace)
+                })?;
+            &key_line[colon + 1..]
+        }
+    };
+    rest.chars()
+        .next()
+        .is_none_or(char::is_whitespace)
+        .then_some(rest)
+}
+
+// 键所在行中定义在值上的锚点名，如 defaults: &defaults；值中引号内的 & 不是锚点
+fn anchor_of(segment: &KeySegment) -> Option<&str> {
+    let key_line = segment
+        .text
+        .lines()
+        .find(|line| !is_blank_or_comment(line))?;
+    let rest = after_key(key_line)?.trim_start().strip_prefix('&')?;
+    let name = rest
+        .split(|c: char| c.is_whitespace() || FLOW_INDICATOR_CHARS.contains(&c))
+        .next()?;
+    (!name.is_empty()).then_some(name)
+}
+
+// 重写键时保留其值上的锚点，使引用它的别名仍然有效
+fn serialize_entry_with_anchor(
+    key: &str,
+    value: &Value,
+    anchor: Option<&str>,
+    style: Option<&YamlStyle>,
+) -> Option<String> {
+    let entry = serialize_entry(key, value, style)?;
+    let Some(anchor) = anchor else {
+        return Some(entry);
+    };
+
+    let serialized_key = serde_yaml_ng::to_string(key).ok()?;
+    let key_len = serialized_key.trim_end().len() + 1;
+    if entry.get(key_len - 1..key_len) != Some(":") {
+        return None;
+    }
+    Some(format!(
+        "{} &{}{}",
+        &entry[..key_len],
+        anchor,
+        &entry[key_len..]
+    ))
+}
+
+// 文本中以 & 定义的锚点或以 * 引用的别名；引号中的文本也可能被当作锚点，只影响排列的顺序
+fn names_after(text: &str, indicator: char) -> impl Iterator<Item = &str> {
+    text.split(indicator).skip(1).filter_map(|rest| {
+        let name = rest
+            .split(|c: char| c.is_whitespace() || FLOW_INDICATOR_CHARS.contains(&c))
+            .next()?;
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+// 调整顺序后别名必须出现在锚点之后，引用了文档中锚点的键推迟到定义该锚点的键之后
+fn order_by_anchors(pieces: Vec<String>) -> Vec<String> {
+    let anchors: AHashHashSet<&str> = pieces
+        .iter()
+        .flat_map(|text| names_after(text, '&'))
+        .collect();
+    let mut defined: AHashHashSet<String> = AHashHashSet::default();
+    let mut ordered = Vec::with_capacity(pieces.len());
+    let mut deferred: Vec<&String> = Vec::new();
+    for piece in &pieces {
+        deferred.push(piece);
+        while let Some(index) = deferred.iter().position(|text| {
+            names_after(text, '*').all(|alias| !anchors.contains(alias) || defined.contains(alias))
+        }) {
+            let text = deferred.remove(index);
+            defined.extend(names_after(text, '&').map(str::to_owned));
+            ordered.push(text.clone());
+        }
+    }
+    // 无法满足的引用（如引号中的文本）保持原来的相对顺序
+    ordered.extend(deferred.into_iter().cloned());
+    ordered
+}
+
+// 原文是否使用了锚点、别名或合并键，此时重新生成整个frontmatter会把它们展开
+// 只检查节点开头（缩进、序列项、键和流式集合的分隔符之后）的 &、* 和 <<，跳过引号和注释中的内容；
+// 多行文本中的误判只会让文件被跳过，不会丢失锚点
+pub fn uses_anchors(original: &str) -> bool {
+    original.lines().any(|line| {
+        let mut node_start = true;
+        let mut quote = None;
+        let mut escaped = false;
+        let mut prev = ' ';
+        for (index, c) in line.char_indices() {
+            if let Some(open) = quote {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' && open == '"' {
+                    escaped = true;
+                } else if c == open {
+                    quote = None;
+                    node_start = false;
+                }
+                prev = c;
+                continue;
+            }
+            match c {
+                '&' | '*' if node_start => return true,
+                '<' if node_start && line[index..].starts_with(YAML_MERGE_KEY) => return true,
+                '"' | '\'' if node_start => quote = Some(c),
+                '#' if prev.is_whitespace() => return false,
+                ' ' | '\t' => {}
+                ':' | '-' | '?' | '[' | '{' | ',' => node_start = true,
+                _ => node_start = false,
+            }
+            prev = c;
+        }
+        false
+    })
+}
+
+// 展开合并键 <<，返回展开后的映射和从合并键继承（未被显式覆盖）的顶层键
+pub fn resolve_merge_keys(
+    raw: &AHashIndexMap<String, Value>,
+) -> Result<(AHashIndexMap<String, Value>, Vec<String>), serde_yaml_ng::Error> {
+    let mut value = serde_yaml_ng::to_value(raw)?;
+    value.apply_merge()?;
+    let mut expanded: AHashIndexMap<String, Value> = serde_yaml_ng::from_value(value)?;
+    // 移除合并键时其后的键顺序会被打乱，按原文中的顺序排列，继承的键排在最后
+    let mut merged: AHashIndexMap<String, Value> = raw
+        .keys()
+        .filter_map(|key| expanded.shift_remove_entry(key))
+        .collect();
+    let inherited = expanded.keys().cloned().collect();
+    merged.extend(expanded);
+    Ok((merged, inherited))
+}
+
+fn parse_merged(content: &str) -> Option<AHashIndexMap<String, Value>> {
+    let raw = serde_yaml_ng::from_str::<AHashIndexMap<String, Value>>(content).ok()?;
+    resolve_merge_keys(&raw).ok().map(|(merged, _)| merged)
+}
+
+// 在原YAML文本上应用修改：未变化的键保持原样，修改的键只重写该键的行，
+// 删除的键连同其注释一起移除，新增的键追加在末尾。
+// reorder 时合并键保持在最前面，其余的键连同注释按 data 的顺序重新排列，
+// 引用了文档中锚点的键（包括合并键）排在定义该锚点的键之后。
+// 传入 style 时重写的键按该格式写出。
+// data 是展开合并键之后的值：合并键和锚点保持不变，修改继承的值时写为显式覆盖。
+// 无法保留格式时返回 None，原文没有锚点时调用方可以重新生成整个frontmatter
+pub fn patch_yaml_frontmatter(
+    original: &str,
+    data: &AHashIndexMap<String, Value>,
+    reorder: bool,
+    style: Option<&YamlStyle>,
+) -> Option<String> {
+    let old = serde_yaml_ng::from_str::<AHashIndexMap<String, Value>>(original).ok()?;
+    let (old_merged, _) = resolve_merge_keys(&old).ok()?;
+    let split = split_top_level_keys(original)?;
+
+    // 切分结果必须与整体解析的键一一对应
+    if split.segments.len() != old.len()
+        || split
+            .segments
+            .iter()
+            .zip(old.keys())
+            .any(|(segment, key)| segment.key != *key)
+    {
+        return None;
+    }
+
+    let mut pieces: Vec<(&str, String)> = Vec::with_capacity(split.segments.len());
+    for segment in &split.segments {
+        // 合并键不出现在 data 中，始终保留
+        if segment.key == YAML_MERGE_KEY {
+            pieces.push((&segment.key, segment.text.clone()));
+            continue;
+        }
+
+        let Some(new_value) = data.get(&segment.key) else {
+            continue; // 已删除的键
+        };
+
+        if old_merged.get(&segment.key) == Some(new_value) {
+            pieces.push((&segment.key, segment.text.clone()));
+            continue;
+        }
+
+        // 保留紧贴在键上方的注释，以及值之后的空行和注释
+        let key_line_start = segment
+            .text
+            .split_inclusive('\n')
+            .take_while(|line| is_blank_or_comment(line))
+            .map(str::len)
+            .sum::<usize>();
+        let tail_start = segment.text.len()
+            - segment
+                .text
+                .split_inclusive('\n')
+                .rev()
+                .take_while(|line| is_blank_or_comment(line))
+                .map(str::len)
+                .sum::<usize>();
+        let mut text = segment.text[..key_line_start].to_owned();
+        text.push_str(&serialize_entry_with_anchor(
+            &segment.key,
+            new_value,
+            anchor_of(segment),
+            style,
+        )?);
+        text.push_str(&segment.text[tail_start.max(key_line_start)..]);
+        pieces.push((&segment.key, text));
+    }
+
+    // 新增的键，以及与修改后展开结果不一致的继承键
+    let kept: String = pieces.iter().map(|(_, text)| text.as_str()).collect();
+    let patched = parse_merged(&format!("{}{}", split.leading, kept))?;
+    for (key, value) in data {
+        if !old.contains_key(key) && patched.get(key) != Some(value) {
+            pieces.push((key, serialize_entry(key, value, style)?));
+        }
+    }
+
+    let mut res = split.leading;
+    if reorder {
+        let mut pieces: AHashIndexMap<&str, String> = pieces.into_iter().collect();
+        let merge = pieces.shift_remove(YAML_MERGE_KEY);
+        let ordered = merge
+            .into_iter()
+            .chain(
+                data.keys()
+                    .filter_map(|key| pieces.shift_remove(key.as_str())),
+            )
+            .collect();
+        for mut text in order_by_anchors(ordered) {
+            // 原来的最后一个键移到中间时补上换行
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            res.push_str(&text);
+        }
+    } else {
+        pieces.iter().for_each(|(_, text)| res.push_str(text));
+    }
+
+    // 展开后必须与要写入的数据完全一致，例如删除继承的键就无法在保留合并键的同时表示
+    (parse_merged(&res)? == *data).then_some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        read_and_parse_file,
+        write::{ContentGenerator, Prepared, WriteOptions},
+    };
+    use std::fs;
+
+    const ORIGINAL: &str = "\
+# 文章信息
+title: 'It''s a title'   # 单引号
+subtitle: \"Line\\tbreak\"
+slug: plain-slug
+
+defaults: &defaults
+  layout: post
+  comments: true
+<<: *defaults
+tags:
+  - a   # 第一个
+  - b
+summary: |
+  First line
+  Second line
+";
+
+    fn data_of(content: &str) -> AHashIndexMap<String, Value> {
+        parse_merged(content).unwrap()
+    }
+
+    #[test]
+    fn unchanged_yaml_is_kept_byte_for_byte() {
+        let data = data_of(ORIGINAL);
+        for reorder in [false, true] {
+            assert_eq!(
+                patch_yaml_frontmatter(ORIGINAL, &data, reorder, None).as_deref(),
+                Some(ORIGINAL)
+            );
+        }
+    }
+
+    #[test]
+    fn only_edited_keys_are_rewritten() {
+        let mut data = data_of(ORIGINAL);
+        data.insert("slug".to_owned(), Value::from("new-slug"));
+        let patched = patch_yaml_frontmatter(ORIGINAL, &data, false, None).unwrap();
+        assert_eq!(
+            patched,
+            ORIGINAL.replace("slug: plain-slug", "slug: new-slug")
+        );
+    }
+
+    #[test]
+    fn anchors_and_merge_keys_are_kept() {
+        // 修改带锚点的键时保留锚点，引用它的合并键随之变化
+        let mut data = data_of(ORIGINAL);
+        let mut defaults = data["defaults"].clone();
+        defaults["layout"] = Value::from("page");
+        data.insert("defaults".to_owned(), defaults);
+        data.insert("layout".to_owned(), Value::from("page"));
+        let patched = patch_yaml_frontmatter(ORIGINAL, &data, false, None).unwrap();
+        assert!(patched.contains("defaults: &defaults\n  layout: page\n"));
+        assert!(patched.contains("<<: *defaults\n"));
+        assert_eq!(data_of(&patched), data);
+
+        // 修改继承的值时写为显式覆盖，合并键保持不变
+        let mut data = data_of(ORIGINAL);
+        data.insert("comments".to_owned(), Value::from(false));
+        let patched = patch_yaml_frontmatter(ORIGINAL, &data, false, None).unwrap();
+        assert_eq!(patched, format!("{}comments: false\n", ORIGINAL));
+    }
+
+    // 引号中的 ": &" 不是锚点，重写该键时不会产生新的锚点
+    #[test]
+    fn ampersands_in_quoted_text_are_not_anchors() {
+        let original = "title: \"a: &b c\"\n'key: &k': &real\n  x: 1\n";
+        let mut data = data_of(original);
+        data.insert("title".to_owned(), Value::from("new"));
+        data["key: &k"]["x"] = Value::from(2);
+        assert_eq!(
+            patch_yaml_frontmatter(original, &data, false, None).as_deref(),
+            Some("title: new\n'key: &k': &real\n  x: 2\n")
+        );
+    }
+
+    #[test]
+    fn anchors_are_found_outside_quotes_and_comments() {
+        assert!(uses_anchors(ORIGINAL));
+        assert!(uses_anchors("list:\n  - &a x\n  - [*a, b]\n"));
+        assert!(uses_anchors("post:\n  <<: {layout: post}\n"));
+        for original in [
+            "title: \"a: &b c\"\n",
+            "title: 'R&D *notes*'\n",
+            "title: R&D  # &c *d\n",
+            "title: Tom & Jerry\n",
+        ] {
+            assert!(!uses_anchors(original), "{}", original);
+        }
+    }
+
+    // 换行符和BOM在写入时还原：没有修改时整个文件保持不变，修改时只有该键所在的行变化
+    #[test]
+    fn crlf_and_bom_are_kept() {
+        let original = format!(
+            "\u{FEFF}---\r\n{}date: 2023-05-01 12:00:00\r\n---\r\nBody\r\n",
+            ORIGINAL.replace('\n', "\r\n")
+        );
+        let path = std::env::temp_dir().join("cyrene-test-yaml-crlf-bom.md");
+        fs::write(&path, &original).unwrap();
+        let file_path = path.to_str().unwrap();
+        let parsed = read_and_parse_file(file_path).frontmatter;
+
+        let generate = |data: &AHashIndexMap<String, Value>| {
+            let options = WriteOptions::default();
+            let Ok(Prepared::Ready(mut generator)) =
+                ContentGenerator::open(file_path, data, &options, None)
+            else {
+                panic!("cannot generate {}", file_path);
+            };
+            let mut output = Vec::new();
+            generator
+                .write_to(&mut std::io::sink(), &mut output)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(generate(&parsed), original);
+        let mut data = parsed.clone();
+        data.insert("slug".to_owned(), Value::from("new-slug"));
+        assert_eq!(
+            generate(&data),
+            original.replace("slug: plain-slug\r\n", "slug: new-slug\r\n")
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
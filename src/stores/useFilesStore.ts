@@ -1,11 +1,14 @@
 import type { TreeNode } from '../utils/buildFileTree'
-import type { postFile } from '../utils/types'
+import type { FileChange, FileRevision, FrontmatterFormat, ParsedFile, postFile, ProjectConfig, WriteOptions, WriteReport, WriteStatus } from '../utils/types'
 import { defineStore } from 'pinia'
+import { buildFileTree } from '../utils/buildFileTree'
+import { getRelativePath } from '../utils/getRelativePath'
 
 export const useFilesStore = defineStore('files', {
   state: () => ({
     basePath: '',
     currentAccessPath: '',
+    config: { extensions: ['md'], include: [], exclude: [], include_hidden: false, respect_ignore_files: true, convert_to_utf8: false, transactional_writes: false, key_order: 'preserve', yaml_style: null } as ProjectConfig,
     files: {} as Record<string, postFile>,
     filetree: [] as TreeNode[],
     ready: {
@@ -24,13 +27,145 @@ export const useFilesStore = defineStore('files', {
     getFileRelativePathList() {
       return Object.values(this.files).map(file => file.relativePath)
     },
+    // 有未保存修改的文件及其 frontmatter
+    getModifiedFileData() {
+      return Object.fromEntries(Object.keys(this.files)
+        .filter(key => this.files[key].modified)
+        .map(key => [key, this.files[key].frontmatter]))
+    },
+    getFilesWithParseError() {
+      return Object.values(this.files).filter(file => file.parseError)
+    },
+    // 已读取且确认没有frontmatter的文件
+    getFilesWithFormat(format: FrontmatterFormat) {
+      return Object.keys(this.files).filter(key => this.files[key].format === format)
+    },
+    getFilesWithoutFrontmatter() {
+      return Object.keys(this.files).filter(key => this.files[key].format === null && !this.files[key].parseError)
+    },
+    setParsedFile(absolutePath: string, relativePath: string, parsed: ParsedFile) {
+      this.files[absolutePath] = { relativePath, frontmatter: {}, modified: false }
+      this.applyParsed(absolutePath, parsed)
+    },
+    applyParsed(absolutePath: string, parsed: ParsedFile) {
+      const file = this.files[absolutePath]
+      file.frontmatter = parsed.frontmatter
+      file.format = parsed.format
+      file.parseError = parsed.error
+      file.encoding = parsed.encoding
+      file.inherited = parsed.inherited
+      file.revision = parsed.revision
+    },
+    // 写入选项，包含项目配置中与保存相关的设置
+    getWriteOptions(options: WriteOptions = {}): WriteOptions {
+      return { convert_to_utf8: this.config.convert_to_utf8, transactional: this.config.transactional_writes, key_order: this.config.key_order, yaml_style: this.config.yaml_style, ...options }
+    },
+    // 保存时传回的读取版本，只包含已读取过的文件
+    getRevisions(absolutePaths: string[]) {
+      return Object.fromEntries(absolutePaths
+        .filter(key => this.files[key]?.revision?.sha256)
+        .map(key => [key, this.files[key].revision as FileRevision]))
+    },
+    applyWriteReport(report: WriteReport) {
+      Object.entries(report.files).forEach(([key, result]) => {
+        const file = this.files[key]
+        if (!file)
+          return
+        if (result.revision) {
+          file.revision = result.revision
+          file.modified = false
+          file.changedOnDisk = false
+          file.diskVersion = undefined
+        }
+        else if (result.conflict) {
+          file.changedOnDisk = true
+          file.diskVersion = result.conflict.current
+        }
+      })
+    },
+    // 写入结果中状态为 status 的文件，返回相对路径和原因
+    getWriteResults(report: WriteReport, status: WriteStatus) {
+      return Object.entries(report.files)
+        .filter(([, result]) => result.status === status)
+        .map(([key, result]) => ({ path: this.files[key]?.relativePath ?? key, kind: result.error_kind, message: result.message }))
+    },
+    // 解决冲突：保留本地修改，下次保存时覆盖磁盘上的版本
+    keepLocalVersion(absolutePath: string) {
+      const file = this.files[absolutePath]
+      if (!file.diskVersion)
+        return
+      file.revision = file.diskVersion.revision
+      file.changedOnDisk = false
+      file.diskVersion = undefined
+    },
+    // 解决冲突：放弃本地修改，使用磁盘上的版本；文件已被删除时从列表中移除
+    useDiskVersion(absolutePath: string) {
+      const file = this.files[absolutePath]
+      if (!file.diskVersion) {
+        delete this.files[absolutePath]
+        this.filetree = buildFileTree(this.getFileRelativePathList())
+        return
+      }
+      this.applyParsed(absolutePath, file.diskVersion)
+      file.modified = false
+      file.changedOnDisk = false
+      file.diskVersion = undefined
+    },
+    // 应用磁盘上的外部变化，有未保存修改的文件保留本地内容并标记
+    applyFileChanges(changes: FileChange[]) {
+      let structureChanged = false
+      const upsert = (path: string, parsed: ParsedFile) => {
+        const existing = this.files[path]
+        if (existing?.modified) {
+          existing.changedOnDisk = true
+          existing.diskVersion = parsed
+          return
+        }
+        structureChanged ||= !existing
+        this.setParsedFile(path, getRelativePath(path, this.basePath), parsed)
+      }
+
+      changes.forEach((change) => {
+        if (change.kind === 'created' || change.kind === 'modified') {
+          upsert(change.path.replace(/\\/g, '/'), change.file)
+        }
+        else if (change.kind === 'deleted') {
+          const path = change.path.replace(/\\/g, '/')
+          if (this.files[path]?.modified) {
+            this.files[path].changedOnDisk = true
+            this.files[path].diskVersion = undefined
+          }
+          else if (this.files[path]) {
+            delete this.files[path]
+            structureChanged = true
+          }
+        }
+        else {
+          const from = change.from.replace(/\\/g, '/')
+          const to = change.to.replace(/\\/g, '/')
+          const previous = this.files[from]
+          delete this.files[from]
+          structureChanged = true
+          upsert(to, change.file)
+          // 未保存的修改跟随文件移动到新路径
+          if (previous?.modified) {
+            this.files[to] = { ...previous, relativePath: getRelativePath(to, this.basePath), changedOnDisk: true, diskVersion: change.file }
+          }
+        }
+      })
+
+      if (structureChanged && this.ready.fileTree) {
+        this.filetree = buildFileTree(this.getFileRelativePathList())
+      }
+    },
     setCurrentAccessPath(relativePath: string) {
       this.currentAccessPath = (this.basePath + relativePath).replace(/\\/g, '/')
     },
     addKeyValueToFrontmatter(keys: string[], value: unknown, regexp: RegExp) {
       let modifiedCnt = 0
       Object.values(this.files).forEach((file) => {
-        if (regexp.test(file.relativePath)) {
+        // 解析失败的文件跳过，避免保存时覆盖原有内容
+        if (regexp.test(file.relativePath) && !file.parseError) {
           keys.forEach((key) => {
             if (!(key in file.frontmatter)) {
               file.frontmatter[key] = value
@@ -46,7 +181,8 @@ export const useFilesStore = defineStore('files', {
     removeKeyFromFrontmatter(keys: string[], regexp: RegExp) {
       let modifiedCnt = 0
       Object.values(this.files).forEach((file) => {
-        if (regexp.test(file.relativePath)) {
+        // 解析失败的文件跳过，避免保存时覆盖原有内容
+        if (regexp.test(file.relativePath) && !file.parseError) {
           keys.forEach((key) => {
             if (key in file.frontmatter) {
               delete file.frontmatter[key]
@@ -62,7 +198,8 @@ export const useFilesStore = defineStore('files', {
     normalizeFrontmatter(sourceKey: string[], targetKey: string, regexp: RegExp) {
       let modifiedCnt = 0
       Object.values(this.files).forEach((file) => {
-        if (regexp.test(file.relativePath)) {
+        // 解析失败的文件跳过，避免保存时覆盖原有内容
+        if (regexp.test(file.relativePath) && !file.parseError) {
           sourceKey.forEach((key) => {
             if (key in file.frontmatter && key !== targetKey) {
               if (file.frontmatter[targetKey] === undefined) {
@@ -1,5 +1,6 @@
 import type { useFilesStore } from '../stores/useFilesStore'
-import { save } from '@tauri-apps/plugin-dialog'
+import { message, save } from '@tauri-apps/plugin-dialog'
+import i18n from '../i18n'
 import { exportFrontmatterToXlsx } from './tauri'
 
 export async function exportToXLSX(filesStore: ReturnType<typeof useFilesStore>) {
@@ -15,12 +16,23 @@ export async function exportToXLSX(filesStore: ReturnType<typeof useFilesStore>)
   })
 
   const temp = {} as Record<string, Record<string, unknown>>
+  const skipped: string[] = []
 
+  // frontmatter无法解析的文件没有可导出的数据，导出空行后再导入会被当作删除所有字段
   filesStore.getFileAbsolutePathList().forEach((key) => {
-    temp[filesStore.files[key].relativePath] = filesStore.files[key].frontmatter
+    const file = filesStore.files[key]
+    if (file.parseError) {
+      skipped.push(file.relativePath)
+      return
+    }
+    temp[file.relativePath] = file.frontmatter
   })
 
   if (selected) {
     await exportFrontmatterToXlsx(temp, selected)
+    if (skipped.length > 0) {
+      const { t } = i18n.global
+      await message(t('export.importExport.exportSkipped', { count: skipped.length, files: skipped.join(', ') }), { title: t('common.warning'), kind: 'warning' })
+    }
   }
 }
@@ -1,14 +1,49 @@
-import { invoke } from '@tauri-apps/api/core'
+import type { UnlistenFn } from '@tauri-apps/api/event'
+import type { FileChange, FileRevision, FrontmatterReport, JournalBatch, ParsedFile, PreviewReport, ProjectConfig, StreamEvent, WriteOptions, WriteReport } from './types'
+import { Channel, invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 
-export async function readAndParseMultipleFrontmatter(filePaths: string[]): Promise<Record<string, Record<string, unknown>>> {
-  return await invoke('read_and_parse_multiple_frontmatter', { filePaths })
+export async function readAndParseMultipleFrontmatter(filePaths: string[], extensions?: string[]): Promise<Record<string, ParsedFile>> {
+  return await invoke('read_and_parse_multiple_frontmatter', { filePaths, extensions })
 }
 
-export async function getAllFilesOfDir(folderPath: string): Promise<string[]> {
-  return await invoke('get_all_files_of_dir', { dir: folderPath })
+export async function getAllFilesOfDir(folderPath: string, extensions?: string[]): Promise<string[]> {
+  return await invoke('get_all_files_of_dir', { dir: folderPath, options: { extensions } })
 }
 
-export async function readAndParseYamlFrontmatter(filePath: string): Promise<Record<string, unknown>> {
+// 在后台扫描并解析目录中的文件，结果分批通过 onEvent 返回，返回值用于取消扫描
+export async function streamFrontmatter(folderPath: string, extensions: string[] | undefined, onEvent: (event: StreamEvent) => void): Promise<number> {
+  const channel = new Channel<StreamEvent>()
+  channel.onmessage = onEvent
+  return await invoke('stream_frontmatter', { dir: folderPath, options: { extensions }, onEvent: channel })
+}
+
+export async function cancelStream(streamId: number): Promise<boolean> {
+  return await invoke('cancel_stream', { streamId })
+}
+
+// 监视目录中文件的变化，再次调用时替换之前监视的目录
+export async function watchDirectory(folderPath: string, extensions?: string[]): Promise<void> {
+  return await invoke('watch_directory', { dir: folderPath, options: { extensions } })
+}
+
+export async function unwatchDirectory(): Promise<void> {
+  return await invoke('unwatch_directory')
+}
+
+export async function onFileChanged(handler: (changes: FileChange[]) => void): Promise<UnlistenFn> {
+  return await listen<FileChange[]>('file-changed', event => handler(event.payload))
+}
+
+export async function scanMissingFrontmatter(folderPath: string, extensions?: string[]): Promise<FrontmatterReport> {
+  return await invoke('scan_missing_frontmatter', { dir: folderPath, options: { extensions } })
+}
+
+export async function loadProjectConfig(basePath: string): Promise<ProjectConfig> {
+  return await invoke('load_project_config', { basePath })
+}
+
+export async function readAndParseYamlFrontmatter(filePath: string): Promise<ParsedFile> {
   return await invoke('read_and_parse_yaml_frontmatter', { filePath })
 }
 
@@ -16,16 +51,40 @@ export async function exportFrontmatterToXlsx(data: Record<string, Record<string
   return await invoke('export_frontmatter_to_xlsx', { data, outputPath })
 }
 
-export async function importFrontmatterFromXlsx(xlsxPath: string, basePath: string): Promise<string> {
-  return await invoke('import_frontmatter_from_xlsx', { xlsxPath, basePath })
+// insertMissing 为 true 时，在表格中没有frontmatter的文件开头插入新的frontmatter，否则跳过这些文件
+export async function importFrontmatterFromXlsx(xlsxPath: string, basePath: string, insertMissing: boolean): Promise<WriteReport> {
+  return await invoke('import_frontmatter_from_xlsx', { xlsxPath, basePath, insertMissing })
+}
+
+// 传入 revisions 时，读取后被其他程序修改过的文件不会被写入，而是作为冲突返回
+export async function writeMultipleFrontmatter(fileData: Record<string, Record<string, unknown>>, extensions?: string[], options?: WriteOptions, revisions?: Record<string, FileRevision>): Promise<WriteReport> {
+  return await invoke('write_multiple_frontmatter', { fileData, extensions, options, revisions })
+}
+
+// 与 writeMultipleFrontmatter 参数相同，只返回每个文件的 diff，不写入文件
+export async function previewWriteFrontmatter(fileData: Record<string, Record<string, unknown>>, extensions?: string[], options?: WriteOptions, revisions?: Record<string, FileRevision>): Promise<PreviewReport> {
+  return await invoke('preview_write_frontmatter', { fileData, extensions, options, revisions })
+}
+
+export async function previewImportFromXlsx(xlsxPath: string, basePath: string, insertMissing: boolean): Promise<PreviewReport> {
+  return await invoke('preview_import_from_xlsx', { xlsxPath, basePath, insertMissing })
+}
+
+export async function listWriteBatches(): Promise<JournalBatch[]> {
+  return await invoke('list_write_batches')
+}
+
+// 撤销或重做一次批量写入；批次之后又被修改过的文件作为冲突返回，force 时仍然覆盖
+export async function undoWriteBatch(batchId: number, force?: boolean): Promise<WriteReport> {
+  return await invoke('undo_write_batch', { batchId, force })
 }
 
-export async function writeMultipleFrontmatter(fileData: Record<string, Record<string, unknown>>): Promise<Record<string, boolean>> {
-  return await invoke('write_multiple_frontmatter', { fileData })
+export async function redoWriteBatch(batchId: number, force?: boolean): Promise<WriteReport> {
+  return await invoke('redo_write_batch', { batchId, force })
 }
 
-export async function backupFilesAsTarZst(fileList: string[], basePath: string, outputPath: string, zstdLevel: number): Promise<string> {
-  return await invoke('backup_files_as_tar_zst', { fileList, basePath, outputPath, zstdLevel })
+export async function backupFilesAsTarZst(fileList: string[], basePath: string, outputPath: string, zstdLevel: number, extensions?: string[]): Promise<string> {
+  return await invoke('backup_files_as_tar_zst', { fileList, basePath, outputPath, zstdLevel, extensions })
 }
 
 export async function restoreFilesFromTarZst(backupPath: string, restoreBasePath: string): Promise<{ success_count: number, failed_count: number, failed_files: string[] }> {
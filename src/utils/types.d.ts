@@ -2,5 +2,151 @@ export interface postFile {
   relativePath: string;
   frontmatter: Record<string, any>;
   modified: boolean;
+  format?: FrontmatterFormat | null;
+  parseError?: ParseError | null;
+  encoding?: string | null;
+  // 通过 YAML 合并键继承的字段
+  inherited?: string[];
+  // 读取时的文件版本，保存时传回后端检查文件是否被其他程序修改
+  revision?: FileRevision;
+  // 读取后文件在磁盘上被其他程序修改或删除，而本地有未保存的修改
+  changedOnDisk?: boolean;
+  // 磁盘上的当前版本，文件被删除时为空
+  diskVersion?: ParsedFile;
   // absolutePath?: string; // Optional, used for file operations
-}
\ No newline at end of file
+}
+
+// 日期时间值，保留文件中的原始文本（包括精度和时区），如 { "!datetime": "2023-05-01 12:00:00" }
+export interface DateTimeValue {
+  '!datetime': string;
+}
+
+export type FrontmatterFormat = 'yaml' | 'toml' | 'json' | 'pelican';
+
+export interface ParseError {
+  kind: 'io' | 'encoding' | 'syntax';
+  message: string;
+  line: number | null;
+  column: number | null;
+}
+
+// 文件内容的 SHA-256 和修改时间（毫秒）
+export interface FileRevision {
+  sha256?: string | null;
+  mtime?: number | null;
+}
+
+export interface ParsedFile {
+  frontmatter: Record<string, unknown>;
+  format: FrontmatterFormat | null;
+  encoding: string | null;
+  inherited: string[];
+  revision: FileRevision;
+  error: ParseError | null;
+}
+
+// 保存时发现文件已被其他程序修改：data 为本次要写入的内容，current 为磁盘上的当前内容
+export interface WriteConflict {
+  data: Record<string, unknown>;
+  current: ParsedFile;
+}
+
+export type WriteStatus = 'written' | 'skipped' | 'conflict' | 'failed'
+
+export type WriteErrorKind = 'not_found' | 'permission_denied' | 'missing_frontmatter' | 'invalid_frontmatter' | 'unlisted_extension' | 'encoding' | 'serialization' | 'cannot_preserve_formatting' | 'transaction_aborted' | 'io'
+
+// 单个文件的写入结果：写入成功时带有新版本，跳过或失败时带有原因，冲突时带有磁盘上的内容
+export interface WriteResult {
+  status: WriteStatus;
+  error_kind: WriteErrorKind | null;
+  message: string | null;
+  revision: FileRevision | null;
+  conflict: WriteConflict | null;
+}
+
+export interface WriteReport {
+  files: Record<string, WriteResult>;
+  summary: Record<WriteStatus, number>;
+  batch: number | null;
+}
+
+export type BatchState = 'applied' | 'undone'
+
+// 写入记录中的一次批量写入，before 和 after 为文件写入前后内容的哈希
+export interface JournalBatch {
+  id: number;
+  created_at: number;
+  state: BatchState;
+  files: Record<string, { before: string, after: string }>;
+}
+
+export interface ProjectConfig {
+  extensions: string[];
+  include: string[];
+  exclude: string[];
+  include_hidden: boolean;
+  respect_ignore_files: boolean;
+  convert_to_utf8: boolean;
+  transactional_writes: boolean;
+  key_order: KeyOrder;
+  yaml_style: YamlStyle | null;
+}
+
+export interface WriteOptions {
+  convert_to_utf8?: boolean;
+  insert_missing?: boolean;
+  insert_format?: FrontmatterFormat;
+  convert_pelican_to_yaml?: boolean;
+  transactional?: boolean;
+  key_order?: KeyOrder;
+  yaml_style?: YamlStyle | null;
+}
+
+// 保存时键的顺序：保持原有顺序、按键名排序，或按列表中的键名排列
+export type KeyOrder = 'preserve' | 'alphabetical' | string[]
+
+export type QuoteStyle = 'single' | 'double'
+
+// 写出YAML frontmatter时使用的格式
+export interface YamlStyle {
+  indent: number;
+  quote: QuoteStyle;
+  flow_sequences: boolean;
+  line_width: number;
+  block_scalars: boolean;
+}
+
+export interface FrontmatterReport {
+  total_files: number;
+  missing_frontmatter: string[];
+}
+
+// 流式扫描通过通道分批发送的事件，一边遍历目录一边解析，文件总数只在 finished 中给出
+export type StreamEvent
+  = | { event: 'started' }
+    | { event: 'chunk', data: { files: Record<string, ParsedFile>, done: number } }
+    | { event: 'finished', data: { done: number, total: number, cancelled: boolean } }
+    | { event: 'failed', data: { message: string } }
+
+// 文件监视推送的外部变化，路径为绝对路径
+export type FileChange
+  = | { kind: 'created', path: string, file: ParsedFile }
+    | { kind: 'modified', path: string, file: ParsedFile }
+    | { kind: 'deleted', path: string }
+    | { kind: 'renamed', from: string, to: string, file: ParsedFile }
+
+// 预览写入：added 表示原文件没有 frontmatter，将插入新的 frontmatter；skipped 与写入时一样按设计跳过
+export type PreviewStatus = 'added' | 'changed' | 'unchanged' | 'skipped' | 'conflict' | 'failed'
+
+export interface FilePreview {
+  status: PreviewStatus;
+  // 统一 diff 格式，编码变化时开头另有一行 "encoding: 原编码 → 新编码"
+  diff: string;
+  error_kind: WriteErrorKind | null;
+  error: string | null;
+}
+
+export interface PreviewReport {
+  files: Record<string, FilePreview>;
+  summary: Record<PreviewStatus, number>;
+}